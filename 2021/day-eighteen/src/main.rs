@@ -2,6 +2,8 @@ use itertools::Itertools;
 use std::fmt::Display;
 use std::ops::AddAssign;
 use std::path::{Component, Path};
+use tracing::{debug, trace};
+use tracing_subscriber::EnvFilter;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 enum Digit {
@@ -83,7 +85,7 @@ impl Number {
     }
     fn check_for_explode(&mut self, nesting_level: u8) -> ReduceAction {
         if nesting_level == 4 {
-            // println!("Explode {}", &self);
+            trace!(pair = %self, "explode");
             return match (&self.0, &self.1) {
                 (&Digit::Lit(left), &Digit::Lit(right)) => ReduceAction::DidExplode(Some(left), Some(right)),
                 _ => unreachable!(),
@@ -119,7 +121,7 @@ impl Number {
         match &mut self.0 {
             d @ Digit::Lit(_) => {
                 if d.split() {
-                    // println!("Split");
+                    trace!(digit = %d, "split");
                     return ReduceAction::DidSplit;
                 }
             }
@@ -132,7 +134,7 @@ impl Number {
         match &mut self.1 {
             d @ Digit::Lit(_) => {
                 if d.split() {
-                    // println!("Split");
+                    trace!(digit = %d, "split");
                     return ReduceAction::DidSplit;
                 }
             }
@@ -146,10 +148,11 @@ impl Number {
     }
 
     fn reduce(&mut self) {
+        let _span = tracing::debug_span!("reduce").entered();
         loop {
-            // println!("{}", &self);
+            trace!(number = %self, "reduction step");
             if self.check_for_explode(0) == ReduceAction::Nothing && self.check_for_split() == ReduceAction::Nothing {
-                // println!("======================================================");
+                debug!(number = %self, "reduced");
                 break;
             }
         }
@@ -197,11 +200,9 @@ mod part1 {
         let nums = parse(input);
         let mut i = nums.into_iter();
         let mut a = i.next().unwrap();
-        // println!("run:a={}", a);
         for n in i {
-            // println!("run:a + {}", n);
+            debug!(sum = %a, addend = %n, "adding");
             a += n;
-            // println!(" = {}", a);
         }
         a
     }
@@ -305,7 +306,21 @@ mod part2 {
     }
 }
 
+// Reductions are only traced when `--explain` is passed or `RUST_LOG` is set
+fn init_tracing() {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) if std::env::args().any(|arg| arg == "--explain") => EnvFilter::new("debug"),
+        Err(_) => return,
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
 fn main() {
+    init_tracing();
     let input_string = include_str!("../input.txt");
     let day_number = Path::new(file!())
         .components()
@@ -331,3 +346,24 @@ fn main() {
     println!("Day {} part 2 - {} - took {} milliseconds.", day_number, part2_ans, now.elapsed().as_millis());
     assert_eq!(part2_ans, 4536);
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn number() -> impl Strategy<Value = Number> {
+        let leaf = any::<usize>().prop_map(Digit::Lit);
+        let digit = leaf.prop_recursive(6, 64, 2, |inner| {
+            (inner.clone(), inner).prop_map(|(l, r)| Digit::Number(Box::new(Number(l, r))))
+        });
+        (digit.clone(), digit).prop_map(|(l, r)| Number(l, r))
+    }
+
+    proptest! {
+        #[test]
+        fn number_round_trips(n in number()) {
+            prop_assert_eq!(parse_number(&n.to_string()), n);
+        }
+    }
+}
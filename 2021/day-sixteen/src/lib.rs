@@ -0,0 +1,150 @@
+use bitvec::mem::BitMemory;
+use bitvec::prelude::*;
+
+#[derive(Debug, PartialEq, Eq)]
+
+pub enum Op {
+    Sum,
+    Product,
+    Maximum,
+    Minimum,
+    Greater,
+    Less,
+    Equal,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Payload {
+    Literal(u64),
+    Operator(Op, Vec<Packet>),
+}
+#[derive(Debug, PartialEq, Eq)]
+pub struct Packet {
+    version: u8,
+    type_id: u8,
+    payload: Payload,
+}
+
+impl Packet {
+    pub fn version_sum(&self) -> usize {
+        match &self.payload {
+            Payload::Literal(_) => self.version as usize,
+            Payload::Operator(_, sub_packets) => {
+                (self.version as usize) + sub_packets.iter().map(|p| p.version_sum()).sum::<usize>()
+            }
+        }
+    }
+    pub fn calculate(&self) -> u64 {
+        match &self.payload {
+            Payload::Literal(value) => *value,
+            Payload::Operator(op_type, sub_packets) => match op_type {
+                Op::Sum => sub_packets.iter().map(Packet::calculate).sum(),
+                Op::Product => sub_packets.iter().map(Packet::calculate).product(),
+                Op::Maximum => sub_packets.iter().map(Packet::calculate).max().unwrap(),
+                Op::Minimum => sub_packets.iter().map(Packet::calculate).min().unwrap(),
+                Op::Greater => (sub_packets[0].calculate() > sub_packets[1].calculate()) as u64,
+                Op::Less => (sub_packets[0].calculate() < sub_packets[1].calculate()) as u64,
+                Op::Equal => (sub_packets[0].calculate() == sub_packets[1].calculate()) as u64,
+            },
+        }
+    }
+}
+
+type Bits = BitSlice<Msb0, u8>;
+
+fn consume_field<T: BitMemory>(bits: &Bits, field_size: usize) -> Option<(T, &Bits)> {
+    if bits.len() < field_size {
+        None
+    } else {
+        let (field_bits, rest) = bits.split_at(field_size);
+        Some((field_bits.load_be::<T>(), rest))
+    }
+}
+
+fn consume_bool(bits: &Bits) -> Option<(bool, &Bits)> {
+    let (a, bits) = consume_field::<u8>(bits, 1)?;
+    Some((a == 1, bits))
+}
+
+fn parse_packet(bits: &Bits) -> Option<(Packet, &Bits)> {
+    if bits.len() < 6 || bits.leading_ones() == bits.leading_zeros() || bits.len() == bits.count_zeros() {
+        return None;
+    }
+    let (version, bits) = consume_field::<u8>(bits, 3)?;
+    let (type_id, mut bits) = consume_field::<u8>(bits, 3)?;
+    let payload = match type_id {
+        4 => {
+            let mut acc = 0;
+            loop {
+                let (prefix, rest) = consume_bool(bits)?;
+                let (nibble, rest) = consume_field::<u64>(rest, 4)?;
+                acc = (acc << 4) + nibble;
+                bits = rest;
+                if !prefix {
+                    break;
+                }
+            }
+            Payload::Literal(acc)
+        }
+        op_id => {
+            let operator = match op_id {
+                0 => Op::Sum,
+                1 => Op::Product,
+                2 => Op::Minimum,
+                3 => Op::Maximum,
+                5 => Op::Greater,
+                6 => Op::Less,
+                7 => Op::Equal,
+                _ => unreachable!(),
+            };
+            let mut sub_packets = Vec::new();
+            let (length_type_id, rest) = consume_bool(bits)?;
+            if length_type_id {
+                let (sub_packet_count, mut sub_packet_bits) = consume_field::<u16>(rest, 11)?;
+                for _ in 0..sub_packet_count {
+                    if let Some((p, rest)) = parse_packet(sub_packet_bits) {
+                        sub_packets.push(p);
+                        sub_packet_bits = rest;
+                    }
+                }
+                bits = sub_packet_bits;
+            } else {
+                let (sub_packet_bit_length, sub_packet_bits) = consume_field::<usize>(rest, 15)?;
+                if sub_packet_bit_length > sub_packet_bits.len() {
+                    return None;
+                }
+                let (mut sub_packet_bits, rest) = sub_packet_bits.split_at(sub_packet_bit_length);
+                bits = rest;
+                while let Some((p, rest)) = parse_packet(sub_packet_bits) {
+                    sub_packets.push(p);
+                    sub_packet_bits = rest;
+                }
+            }
+            Payload::Operator(operator, sub_packets)
+        }
+    };
+    Some((Packet { version, type_id, payload }, bits))
+}
+
+pub fn parse_packets(bytes: &[u8]) -> Vec<Packet> {
+    let mut bits = bytes.view_bits::<Msb0>();
+    let mut packets = Vec::new();
+    while let Some((p, rest)) = parse_packet(bits) {
+        packets.push(p);
+        bits = rest;
+    }
+    packets
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn parse_packets_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            let _ = parse_packets(&bytes);
+        }
+    }
+}
@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    aoc2022::day13::fuzz_parse(&String::from_utf8_lossy(data));
+});
@@ -0,0 +1,345 @@
+//! Batch mode: runs every day against every account's puzzle input.
+//!
+//! Inputs live in a tree shaped like `<root>/<account>/<year>/<day>.txt`. Next to each input there may be a
+//! `<day>.answers` file holding the accepted part 1 answer on its first line and the part 2 answer on the
+//! remaining lines; without one the answers are reported but can't be verified.
+
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+pub const YEAR: usize = 2022;
+
+pub type Solver = fn(&str) -> (String, String);
+
+fn answers<T: Display, U: Display>((part1, part2): (T, U)) -> (String, String) {
+    (part1.to_string(), part2.to_string())
+}
+
+pub fn solver(day_number: usize) -> Option<Solver> {
+    let solver: Solver = match day_number {
+        1 => |s| answers(day01::solve(s)),
+        2 => |s| answers(day02::solve(s)),
+        3 => |s| answers(day03::solve(s)),
+        4 => |s| answers(day04::solve(s)),
+        5 => |s| answers(day05::solve(s)),
+        6 => |s| answers(day06::solve(s)),
+        7 => |s| answers(day07::solve(s)),
+        8 => |s| answers(day08::solve(s)),
+        9 => |s| answers(day09::solve(s)),
+        10 => |s| answers(day10::solve(s)),
+        11 => |s| answers(day11::solve(s)),
+        12 => |s| answers(day12::solve(s)),
+        13 => |s| answers(day13::solve(s)),
+        14 => |s| answers(day14::solve(s)),
+        15 => |s| answers(day15::solve(s)),
+        16 => |s| answers(day16::solve(s)),
+        17 => |s| answers(day17::solve(s)),
+        18 => |s| answers(day18::solve(s)),
+        19 => |s| answers(day19::solve(s)),
+        20 => |s| answers(day20::solve(s)),
+        21 => |s| answers(day21::solve(s)),
+        22 => |s| answers(day22::solve(s)),
+        23 => |s| answers(day23::solve(s)),
+        24 => |s| answers(day24::solve(s)),
+        25 => |s| answers(day25::solve(s)),
+        _ => return None,
+    };
+    Some(solver)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Correct,
+    Incorrect,
+    Unverified,
+    Panicked,
+    /// The input or its answers couldn't be read
+    Unreadable,
+    NotImplemented,
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Verdict::Correct => "ok",
+            Verdict::Incorrect => "WRONG",
+            Verdict::Unverified => "?",
+            Verdict::Panicked => "PANIC",
+            Verdict::Unreadable => "UNREADABLE",
+            Verdict::NotImplemented => "-",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartResult {
+    pub answer: Option<String>,
+    pub verdict: Verdict,
+}
+
+impl PartResult {
+    pub(crate) fn judge(answer: String, expected: Option<&str>) -> Self {
+        let verdict = match expected {
+            Some(expected) if expected.trim_end() == answer.trim_end() => Verdict::Correct,
+            Some(_) => Verdict::Incorrect,
+            None => Verdict::Unverified,
+        };
+        PartResult { answer: Some(answer), verdict }
+    }
+
+    fn without_answer(verdict: Verdict) -> Self {
+        PartResult { answer: None, verdict }
+    }
+}
+
+impl Display for PartResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.answer {
+            // Multi-line answers (e.g. day 10's CRT output) would wreck the table layout
+            Some(answer) if answer.trim_end().contains('\n') => {
+                write!(f, "<{} lines> [{}]", answer.lines().count(), self.verdict)
+            }
+            Some(answer) => write!(f, "{answer} [{}]", self.verdict),
+            None => write!(f, "[{}]", self.verdict),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayResult {
+    pub account: String,
+    pub year: usize,
+    pub day: usize,
+    pub parts: [PartResult; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Input {
+    account: String,
+    year: usize,
+    day: usize,
+    path: PathBuf,
+}
+
+fn sub_directories(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    dirs.retain(|p| p.is_dir());
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn file_name(p: &Path) -> Option<&str> {
+    p.file_name().and_then(|name| name.to_str())
+}
+
+fn find_inputs(root: &Path) -> io::Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for account_dir in sub_directories(root)? {
+        let Some(account) = file_name(&account_dir) else { continue };
+        for year_dir in sub_directories(&account_dir)? {
+            let Some(year) = file_name(&year_dir).and_then(|y| y.parse::<usize>().ok()) else { continue };
+            for entry in fs::read_dir(&year_dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                    continue;
+                }
+                let day = path
+                    .file_stem()
+                    .and_then(|d| d.to_str())
+                    .and_then(|d| d.parse::<usize>().ok());
+                if let Some(day) = day {
+                    inputs.push(Input { account: account.to_owned(), year, day, path });
+                }
+            }
+        }
+    }
+    inputs.sort_by(|l, r| (l.year, l.day, &l.account).cmp(&(r.year, r.day, &r.account)));
+    Ok(inputs)
+}
+
+pub(crate) fn read_expected_answers(input_path: &Path) -> io::Result<[Option<String>; 2]> {
+    match fs::read_to_string(input_path.with_extension("answers")) {
+        Ok(s) => {
+            let (part1, part2) = s.split_once('\n').unwrap_or((&s, ""));
+            let non_empty = |a: &str| (!a.trim().is_empty()).then(|| a.to_owned());
+            Ok([non_empty(part1), non_empty(part2)])
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok([None, None]),
+        Err(e) => Err(e),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+fn read_input(path: &Path) -> io::Result<(String, [Option<String>; 2])> {
+    Ok((fs::read_to_string(path)?, read_expected_answers(path)?))
+}
+
+fn run_input(input: &Input) -> DayResult {
+    let parts = match solver(input.day).filter(|_| input.year == YEAR) {
+        None => {
+            [PartResult::without_answer(Verdict::NotImplemented), PartResult::without_answer(Verdict::NotImplemented)]
+        }
+        Some(solve) => match read_input(&input.path) {
+            Err(e) => {
+                eprintln!("{} {} day {} couldn't be read: {e}", input.account, input.year, input.day);
+                [PartResult::without_answer(Verdict::Unreadable), PartResult::without_answer(Verdict::Unreadable)]
+            }
+            Ok((puzzle_input, [expected1, expected2])) => {
+                // Profiling each run keeps the per-part allocation records from piling up over the whole batch
+                match panic::catch_unwind(|| profile::profile(|| solve(&puzzle_input)).0) {
+                    Ok((part1, part2)) => {
+                        [PartResult::judge(part1, expected1.as_deref()), PartResult::judge(part2, expected2.as_deref())]
+                    }
+                    Err(payload) => {
+                        eprintln!(
+                            "{} {} day {} panicked: {}",
+                            input.account,
+                            input.year,
+                            input.day,
+                            panic_message(&*payload)
+                        );
+                        [PartResult::without_answer(Verdict::Panicked), PartResult::without_answer(Verdict::Panicked)]
+                    }
+                }
+            }
+        },
+    };
+    DayResult { account: input.account.clone(), year: input.year, day: input.day, parts }
+}
+
+/// Runs every input found under `root`, in year/day/account order.
+pub fn run(root: &Path) -> io::Result<Vec<DayResult>> {
+    // Panics and unreadable inputs are reported as verdicts, so that one bad input doesn't stop the rest. The panic
+    // hook is left alone, since it belongs to the whole process and anything else running at the same time would lose
+    // its panic messages too.
+    Ok(find_inputs(root)?.iter().map(run_input).collect())
+}
+
+/// A table with one row per year/day/part and one column per account.
+pub struct Matrix<'a>(pub &'a [DayResult]);
+
+impl Display for Matrix<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let accounts = self
+            .0
+            .iter()
+            .map(|r| r.account.as_str())
+            .collect::<BTreeSet<_>>();
+        let days = self
+            .0
+            .iter()
+            .map(|r| (r.year, r.day))
+            .collect::<BTreeSet<_>>();
+
+        let mut rows = vec![std::iter::once("".to_owned())
+            .chain(accounts.iter().map(|a| a.to_string()))
+            .collect::<Vec<_>>()];
+        for (year, day) in &days {
+            for part in 0..2 {
+                let mut row = vec![format!("{year} day {day:02} part {}", part + 1)];
+                for account in &accounts {
+                    let cell = self
+                        .0
+                        .iter()
+                        .find(|r| r.year == *year && r.day == *day && r.account == *account)
+                        .map_or_else(String::new, |r| r.parts[part].to_string());
+                    row.push(cell);
+                }
+                rows.push(row);
+            }
+        }
+
+        let widths = (0..rows[0].len())
+            .map(|col| {
+                rows.iter()
+                    .map(|row| row[col].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join(" | ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        let verdicts = self
+            .0
+            .iter()
+            .flat_map(|r| r.parts.iter().map(|p| p.verdict))
+            .collect::<Vec<_>>();
+        let count = |v: Verdict| verdicts.iter().filter(|verdict| **verdict == v).count();
+        writeln!(
+            f,
+            "{} correct, {} wrong, {} unverified, {} panicked, {} unreadable",
+            count(Verdict::Correct),
+            count(Verdict::Incorrect),
+            count(Verdict::Unverified),
+            count(Verdict::Panicked),
+            count(Verdict::Unreadable)
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_batch() {
+    let root = std::env::temp_dir().join(format!("aoc2022-batch-{}", std::process::id()));
+    let write = |path: &str, contents: &str| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    };
+    write("alice/2022/01.txt", "1000\n2000\n\n4000\n\n5000\n6000\n\n7000\n8000\n9000\n\n10000\n");
+    write("alice/2022/01.answers", "24000\n45000\n");
+    write("bob/2022/01.txt", "1\n\n2\n\n3\n");
+    write("bob/2022/01.answers", "3\n7\n");
+    write("bob/2022/02.txt", "A Y\n");
+    write("bob/2022/notes.md", "ignored");
+    fs::create_dir_all(root.join("carol/2022")).unwrap();
+    fs::write(root.join("carol/2022/01.txt"), [0xff, 0xfe]).unwrap();
+    write("carol/2022/02.txt", "A Y\n");
+    fs::create_dir_all(root.join("carol/2022/02.answers")).unwrap();
+
+    let results = run(&root);
+    fs::remove_dir_all(&root).unwrap();
+    let results = results.unwrap();
+
+    assert_eq!(results.len(), 5);
+    assert_eq!((results[0].account.as_str(), results[0].day), ("alice", 1));
+    assert_eq!(results[0].parts[0], PartResult { answer: Some("24000".to_owned()), verdict: Verdict::Correct });
+    assert_eq!(results[0].parts[1].verdict, Verdict::Correct);
+    assert_eq!((results[1].account.as_str(), results[1].day), ("bob", 1));
+    assert_eq!(results[1].parts[0].verdict, Verdict::Correct);
+    assert_eq!(results[1].parts[1], PartResult { answer: Some("6".to_owned()), verdict: Verdict::Incorrect });
+    assert_eq!((results[2].account.as_str(), results[2].day), ("carol", 1));
+    assert_eq!(results[2].parts[0], PartResult { answer: None, verdict: Verdict::Unreadable });
+    assert_eq!(results[3].parts[0], PartResult { answer: Some("8".to_owned()), verdict: Verdict::Unverified });
+    assert_eq!((results[4].account.as_str(), results[4].day), ("carol", 2));
+    assert_eq!(results[4].parts[1].verdict, Verdict::Unreadable);
+
+    let matrix = Matrix(&results).to_string();
+    assert!(matrix.contains("2022 day 01 part 2 | 45000 [ok] | 6 [WRONG] | [UNREADABLE]"), "{matrix}");
+    assert!(matrix.ends_with("3 correct, 1 wrong, 2 unverified, 0 panicked, 4 unreadable\n"), "{matrix}");
+}
@@ -0,0 +1,462 @@
+//! Fetches puzzle inputs and submits answers to the Advent of Code website.
+//!
+//! Requests are authenticated with the `session` cookie from a logged-in browser. Inputs never change, so they're
+//! cached on disk after the first download, as are the verdicts for submitted answers so the same wrong answer is
+//! never sent twice. Requests are spaced at least [`Client::with_min_interval`] apart to go easy on the server, even
+//! across separate runs, as the time of the last one is kept in the cache directory.
+//! The base URL can be pointed at a local stub server for testing.
+
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_BASE_URL: &str = "https://adventofcode.com";
+pub const DEFAULT_CACHE_DIR: &str = ".cache";
+const USER_AGENT: &str = "aoc2022 runner";
+
+#[derive(Debug)]
+pub enum ClientError {
+    MissingSession,
+    Io(io::Error),
+    Http(String),
+    Status(u16, String),
+    UnrecognisedResponse(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::MissingSession => write!(f, "no session token, set AOC_SESSION"),
+            ClientError::Io(e) => write!(f, "cache error: {e}"),
+            ClientError::Http(e) => write!(f, "request failed: {e}"),
+            ClientError::Status(code, body) => write!(f, "server returned {code}: {}", body.trim()),
+            ClientError::UnrecognisedResponse(body) => write!(f, "couldn't understand the response: {body}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<ureq::Error> for ClientError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(code, response) => {
+                ClientError::Status(code, response.into_string().unwrap_or_default())
+            }
+            ureq::Error::Transport(transport) => ClientError::Http(transport.to_string()),
+        }
+    }
+}
+
+/// What the server made of a submitted answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Correct,
+    TooHigh,
+    TooLow,
+    Incorrect,
+    /// Answers were submitted too recently; try again after this long.
+    Wait(Duration),
+    /// The part has already been solved, or part 2 isn't unlocked yet.
+    WrongLevel,
+}
+
+impl Verdict {
+    /// Parses the article in the page returned after submitting an answer.
+    pub fn from_response(body: &str) -> Option<Self> {
+        let verdict = if body.contains("That's the right answer") {
+            Verdict::Correct
+        } else if body.contains("You gave an answer too recently") {
+            Verdict::Wait(parse_wait(body)?)
+        } else if body.contains("your answer is too high") {
+            Verdict::TooHigh
+        } else if body.contains("your answer is too low") {
+            Verdict::TooLow
+        } else if body.contains("That's not the right answer") {
+            Verdict::Incorrect
+        } else if body.contains("You don't seem to be solving the right level") {
+            Verdict::WrongLevel
+        } else {
+            return None;
+        };
+        Some(verdict)
+    }
+
+    /// Whether submitting the same answer again could give a different verdict.
+    fn is_final(&self) -> bool {
+        !matches!(self, Verdict::Wait(_) | Verdict::WrongLevel)
+    }
+}
+
+// e.g. "You have 1m 23s left to wait." or "You have 45s left to wait."
+fn parse_wait(body: &str) -> Option<Duration> {
+    let (before, _) = body.split_once(" left to wait")?;
+    let (_, time) = before
+        .rsplit_once("You have ")
+        .or_else(|| before.rsplit_once("you have "))?;
+    time.split_whitespace()
+        .map(|component| {
+            let (value, unit) = component.split_at(component.find(|c: char| !c.is_ascii_digit())?);
+            let value = value.parse::<u64>().ok()?;
+            match unit {
+                "h" => Some(value * 3600),
+                "m" => Some(value * 60),
+                "s" => Some(value),
+                _ => None,
+            }
+        })
+        .sum::<Option<u64>>()
+        .map(Duration::from_secs)
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Correct => write!(f, "correct"),
+            Verdict::TooHigh => write!(f, "too high"),
+            Verdict::TooLow => write!(f, "too low"),
+            Verdict::Incorrect => write!(f, "incorrect"),
+            Verdict::Wait(duration) => write!(f, "wait {}s", duration.as_secs()),
+            Verdict::WrongLevel => write!(f, "wrong level"),
+        }
+    }
+}
+
+impl FromStr for Verdict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "correct" => Ok(Verdict::Correct),
+            "too high" => Ok(Verdict::TooHigh),
+            "too low" => Ok(Verdict::TooLow),
+            "incorrect" => Ok(Verdict::Incorrect),
+            "wrong level" => Ok(Verdict::WrongLevel),
+            _ => s
+                .strip_prefix("wait ")
+                .and_then(|w| w.strip_suffix('s'))
+                .and_then(|w| w.parse().ok())
+                .map(|secs| Verdict::Wait(Duration::from_secs(secs)))
+                .ok_or_else(|| format!("Bad verdict {s}")),
+        }
+    }
+}
+
+pub struct Client {
+    agent: ureq::Agent,
+    session: String,
+    base_url: String,
+    cache_dir: PathBuf,
+    min_interval: Duration,
+}
+
+impl Client {
+    pub fn new(session: &str) -> Self {
+        Client {
+            agent: ureq::AgentBuilder::new()
+                .user_agent(USER_AGENT)
+                .timeout(Duration::from_secs(30))
+                .build(),
+            session: session.trim().to_owned(),
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            cache_dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            min_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Builds a client from `AOC_SESSION`, honouring `AOC_BASE_URL` and `AOC_CACHE_DIR` if they're set.
+    pub fn from_env() -> Result<Self, ClientError> {
+        let session = std::env::var("AOC_SESSION").map_err(|_| ClientError::MissingSession)?;
+        let mut client = Client::new(&session);
+        if let Ok(base_url) = std::env::var("AOC_BASE_URL") {
+            client = client.with_base_url(&base_url);
+        }
+        if let Ok(cache_dir) = std::env::var("AOC_CACHE_DIR") {
+            client = client.with_cache_dir(cache_dir);
+        }
+        Ok(client)
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    // Waits until the last request from any client sharing the cache is far enough in the past, in milliseconds
+    // since the epoch so that other runs can read it
+    fn throttle(&self) -> Result<(), ClientError> {
+        let path = self.cache_dir.join("last-request");
+        let last_request = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+        if let Some(last_request) = last_request {
+            // A request from the future, after the clock has gone back, counts as just now
+            let elapsed = SystemTime::now()
+                .duration_since(last_request)
+                .unwrap_or(Duration::ZERO);
+            if elapsed < self.min_interval {
+                thread::sleep(self.min_interval - elapsed);
+            }
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        fs::create_dir_all(&self.cache_dir)?;
+        fs::write(&path, now.as_millis().to_string())?;
+        Ok(())
+    }
+
+    fn day_url(&self, year: usize, day: usize) -> String {
+        format!("{}/{year}/day/{day}", self.base_url)
+    }
+
+    fn cache_path(&self, year: usize, day: usize, extension: &str) -> PathBuf {
+        self.cache_dir
+            .join(year.to_string())
+            .join(format!("{day:02}.{extension}"))
+    }
+
+    /// Returns the puzzle input for a day, downloading it only if it isn't already cached.
+    pub fn input(&self, year: usize, day: usize) -> Result<String, ClientError> {
+        let path = self.cache_path(year, day, "txt");
+        if let Ok(input) = fs::read_to_string(&path) {
+            return Ok(input);
+        }
+        self.throttle()?;
+        let input = self
+            .agent
+            .get(&format!("{}/input", self.day_url(year, day)))
+            .set("Cookie", &format!("session={}", self.session))
+            .call()?
+            .into_string()?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, &input)?;
+        Ok(input)
+    }
+
+    fn cached_verdicts(&self, year: usize, day: usize) -> Result<Vec<(usize, String, Verdict)>, ClientError> {
+        let path = self.cache_path(year, day, "verdicts");
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        contents
+            .lines()
+            .map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let (Some(part), Some(answer), Some(verdict)) = (fields.next(), fields.next(), fields.next()) else {
+                    return Err(ClientError::Io(io::Error::new(io::ErrorKind::InvalidData, line.to_owned())));
+                };
+                let part = part
+                    .parse::<usize>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let verdict = verdict
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok((part, answer.to_owned(), verdict))
+            })
+            .collect()
+    }
+
+    /// Submits an answer, unless the same answer has already had a final verdict or the part is already solved.
+    pub fn submit(&self, year: usize, day: usize, part: usize, answer: &str) -> Result<Verdict, ClientError> {
+        let answer = answer.trim();
+        let cached = self.cached_verdicts(year, day)?;
+        let mut previous = cached.iter().filter(|(p, _, _)| *p == part);
+        if let Some((_, _, verdict)) = previous.clone().find(|(_, a, _)| a == answer) {
+            return Ok(*verdict);
+        }
+        if previous.any(|(_, _, verdict)| *verdict == Verdict::Correct) {
+            return Ok(Verdict::WrongLevel);
+        }
+
+        self.throttle()?;
+        let body = self
+            .agent
+            .post(&format!("{}/answer", self.day_url(year, day)))
+            .set("Cookie", &format!("session={}", self.session))
+            .send_form(&[("level", &part.to_string()), ("answer", answer)])?
+            .into_string()?;
+        let verdict = Verdict::from_response(&body).ok_or(ClientError::UnrecognisedResponse(body))?;
+
+        if verdict.is_final() {
+            let path = self.cache_path(year, day, "verdicts");
+            fs::create_dir_all(path.parent().unwrap())?;
+            let mut contents = fs::read_to_string(&path).unwrap_or_default();
+            contents.push_str(&format!("{part}\t{answer}\t{verdict}\n"));
+            fs::write(&path, contents)?;
+        }
+        Ok(verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Serves `responses` in order, one per connection, sending each request line and body back over the channel.
+    fn stub_server(responses: Vec<&'static str>) -> (String, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for (response, stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                let mut cookie = String::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        "cookie" => cookie = value.trim().to_owned(),
+                        _ => (),
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                assert_eq!(cookie, "session=secret");
+                sender
+                    .send((request_line.trim().to_owned(), String::from_utf8(body).unwrap()))
+                    .unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+        (base_url, receiver)
+    }
+
+    fn client(base_url: &str, name: &str) -> Client {
+        let cache_dir = std::env::temp_dir().join(format!("aoc2022-client-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        Client::new("secret\n")
+            .with_base_url(base_url)
+            .with_cache_dir(cache_dir)
+            .with_min_interval(Duration::ZERO)
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let article = |s: &str| format!("<main>\n<article><p>{s}</p></article>\n</main>");
+        assert_eq!(
+            Verdict::from_response(&article("That's the right answer! You are one gold star closer.")),
+            Some(Verdict::Correct)
+        );
+        assert_eq!(
+            Verdict::from_response(&article("That's not the right answer; your answer is too high.")),
+            Some(Verdict::TooHigh)
+        );
+        assert_eq!(
+            Verdict::from_response(&article("That's not the right answer; your answer is too low.")),
+            Some(Verdict::TooLow)
+        );
+        assert_eq!(
+            Verdict::from_response(&article("That's not the right answer. If you're stuck, ...")),
+            Some(Verdict::Incorrect)
+        );
+        assert_eq!(
+            Verdict::from_response(&article("You gave an answer too recently. You have 1m 23s left to wait.")),
+            Some(Verdict::Wait(Duration::from_secs(83)))
+        );
+        assert_eq!(
+            Verdict::from_response(&article("You gave an answer too recently. You have 45s left to wait.")),
+            Some(Verdict::Wait(Duration::from_secs(45)))
+        );
+        assert_eq!(
+            Verdict::from_response(&article("You don't seem to be solving the right level.")),
+            Some(Verdict::WrongLevel)
+        );
+        assert_eq!(Verdict::from_response(&article("Something else")), None);
+        for verdict in [Verdict::Correct, Verdict::TooLow, Verdict::Wait(Duration::from_secs(61))] {
+            assert_eq!(verdict.to_string().parse(), Ok(verdict));
+        }
+    }
+
+    #[test]
+    fn test_input_is_cached() {
+        let (base_url, requests) = stub_server(vec!["1\n2\n3\n"]);
+        let client = client(&base_url, "input");
+        assert_eq!(client.input(2022, 1).unwrap(), "1\n2\n3\n");
+        assert_eq!(client.input(2022, 1).unwrap(), "1\n2\n3\n");
+        assert_eq!(requests.recv().unwrap().0, "GET /2022/day/1/input HTTP/1.1");
+        assert!(requests.try_recv().is_err());
+        fs::remove_dir_all(&client.cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_throttle_spans_clients() {
+        let (base_url, requests) = stub_server(vec!["1\n", "2\n"]);
+        let first = client(&base_url, "throttle").with_min_interval(Duration::from_millis(300));
+        assert_eq!(first.input(2022, 1).unwrap(), "1\n");
+        // A second client, as in a later run, shares the first one's cache and so waits for it
+        let second = Client::new("secret")
+            .with_base_url(&base_url)
+            .with_cache_dir(&first.cache_dir)
+            .with_min_interval(Duration::from_millis(300));
+        let started = std::time::Instant::now();
+        assert_eq!(second.input(2022, 2).unwrap(), "2\n");
+        assert!(started.elapsed() >= Duration::from_millis(200), "{:?}", started.elapsed());
+        assert_eq!(requests.iter().count(), 2);
+        fs::remove_dir_all(&first.cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_submit() {
+        let (base_url, requests) = stub_server(vec![
+            "<article>That's not the right answer; your answer is too low.</article>",
+            "<article>You gave an answer too recently. You have 30s left to wait.</article>",
+            "<article>That's the right answer!</article>",
+        ]);
+        let client = client(&base_url, "submit");
+        assert_eq!(client.submit(2022, 1, 1, "41").unwrap(), Verdict::TooLow);
+        assert_eq!(
+            requests.recv().unwrap(),
+            ("POST /2022/day/1/answer HTTP/1.1".to_owned(), "level=1&answer=41".to_owned())
+        );
+        // Known verdicts come from the cache
+        assert_eq!(client.submit(2022, 1, 1, "41").unwrap(), Verdict::TooLow);
+        // Waits aren't cached, so the retry is sent
+        assert_eq!(client.submit(2022, 1, 1, "42").unwrap(), Verdict::Wait(Duration::from_secs(30)));
+        assert_eq!(client.submit(2022, 1, 1, "42").unwrap(), Verdict::Correct);
+        assert_eq!(requests.iter().count(), 2);
+        // The part is solved now, so nothing more is sent
+        assert_eq!(client.submit(2022, 1, 1, "43").unwrap(), Verdict::WrongLevel);
+        fs::remove_dir_all(&client.cache_dir).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use super::profile;
 use itertools::Itertools;
 
 type Calories = Vec<usize>;
@@ -48,10 +49,14 @@ fn test_part2() {
     assert_eq!(find_top_3_calories(&mut c), 45_000);
 }
 
-pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
+pub fn solve(input_string: &str) -> (usize, usize) {
     let mut c = get_calories(input_string);
-    let part1_answer = find_max_calories(&c);
-    let part2_answer = find_top_3_calories(&mut c);
+    let part1_answer = profile::part("part 1", || find_max_calories(&c));
+    let part2_answer = profile::part("part 2", || find_top_3_calories(&mut c));
+    (part1_answer, part2_answer)
+}
+
+pub fn run() -> Option<(usize, bool, usize, bool)> {
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 71_924, part2_answer, part2_answer == 210_406))
 }
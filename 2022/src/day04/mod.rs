@@ -1,3 +1,5 @@
+use super::profile;
+
 fn evaluate(s: &str, f: fn((usize, usize), (usize, usize)) -> bool) -> usize {
     s.lines()
         .filter(|line| {
@@ -40,9 +42,13 @@ fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 4);
 }
 
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 477, part2_answer, part2_answer == 830))
 }
@@ -1,84 +1,262 @@
+use super::profile;
 use std::string::String;
 use std::vec::Vec;
 
-type Stack = Vec<char>;
-type Stacks = Vec<Stack>;
+// Crates can be labelled with any number of characters
+pub type Stack = Vec<String>;
+pub type Stacks = Vec<Stack>;
 
-#[derive(Debug)]
-struct Move {
-    from: usize,
-    to: usize,
-    count: usize,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub count: usize,
+    /// Where the move came from in the input, for reporting problems with it
+    pub line: usize,
 }
-type Moves = Vec<Move>;
+pub type Moves = Vec<Move>;
 
-fn parse_stacks(s: &str) -> Stacks {
-    let mut line_iter = s.lines().rev();
-    let stack_count = line_iter.next().unwrap().split_whitespace().count();
-    let mut stacks = vec![Stack::new(); stack_count];
-    for line in line_iter {
-        for (stack_index, stack) in stacks.iter_mut().enumerate() {
-            let string_index = (stack_index * 4) + 1;
-            let _crate = line.chars().nth(string_index).unwrap();
-            if !_crate.is_whitespace() {
-                stack.push(_crate);
-            }
+/// How a crane moves crates between stacks. Moves are checked before they get here, so there are always at least
+/// `count` crates on `from`.
+pub trait Crane {
+    fn move_crates(&self, from: &mut Stack, to: &mut Stack, count: usize);
+}
+
+/// The CrateMover 9000
+pub struct OneAtATime;
+
+impl Crane for OneAtATime {
+    fn move_crates(&self, from: &mut Stack, to: &mut Stack, count: usize) {
+        for _ in 0..count {
+            to.push(from.pop().unwrap());
         }
     }
-    stacks
 }
 
-fn parse_move(s: &str) -> Move {
-    let bits = s.split_whitespace().collect::<Vec<_>>();
-    assert!(bits.len() == 6, "Wibble - {s} => {bits:?}");
-    Move { count: bits[1].parse().unwrap(), from: bits[3].parse().unwrap(), to: bits[5].parse().unwrap() }
+/// The CrateMover 9001
+pub struct Batch;
+
+impl Crane for Batch {
+    fn move_crates(&self, from: &mut Stack, to: &mut Stack, count: usize) {
+        let split_pos = from.len() - count;
+        to.append(&mut from.split_off(split_pos));
+    }
+}
+
+/// Lifts them all at once, but turns them over on the way
+pub struct ReversedBatch;
+
+impl Crane for ReversedBatch {
+    fn move_crates(&self, from: &mut Stack, to: &mut Stack, count: usize) {
+        let split_pos = from.len() - count;
+        to.extend(from.split_off(split_pos).into_iter().rev());
+    }
 }
 
-fn parse_moves(s: &str) -> Moves {
-    s.lines().map(parse_move).collect::<_>()
+/// Moves up to `capacity` crates at a time, keeping each load in order
+pub struct CapacityLimited {
+    capacity: usize,
 }
 
-fn perform_move_part1(stacks: &mut Stacks, m: &Move) {
-    for _ in 0..m.count {
-        if let Some(char_to_move) = stacks[m.from - 1].pop() {
-            stacks[m.to - 1].push(char_to_move);
-        } else {
-            panic!("Empty stack!!!!");
+impl CapacityLimited {
+    pub fn new(capacity: usize) -> Result<Self, String> {
+        match capacity {
+            0 => Err("A crane has to be able to lift at least one crate".into()),
+            capacity => Ok(Self { capacity }),
         }
     }
 }
 
-fn perform_move_part2(stacks: &mut Stacks, m: &Move) {
-    let split_pos = stacks[m.from - 1].len() - m.count;
-    let mut to_move = stacks[m.from - 1].split_off(split_pos);
-    stacks[m.to - 1].append(&mut to_move);
+impl Crane for CapacityLimited {
+    fn move_crates(&self, from: &mut Stack, to: &mut Stack, count: usize) {
+        let mut remaining = count;
+        while remaining > 0 {
+            let load = remaining.min(self.capacity);
+            Batch.move_crates(from, to, load);
+            remaining -= load;
+        }
+    }
+}
+
+// Every arrangement the stacks have been in, either side of the current one
+#[derive(Default)]
+struct History {
+    undo: Vec<Stacks>,
+    redo: Vec<Stacks>,
+}
+
+/// The stacks, and optionally every arrangement they've been in so that moves can be undone and redone.
+pub struct Yard {
+    stacks: Stacks,
+    history: Option<History>,
 }
 
-fn parse(s: &str) -> (Stacks, Moves) {
-    if let Some((stacks, moves)) = s.replace("\r\n", "\n").split_once("\n\n") {
-        (parse_stacks(stacks), parse_moves(moves))
-    } else {
-        (Vec::new(), Vec::new())
+impl Yard {
+    pub fn new(stacks: Stacks) -> Self {
+        Self { stacks, history: None }
+    }
+
+    pub fn with_history(stacks: Stacks) -> Self {
+        Self { stacks, history: Some(History::default()) }
+    }
+
+    pub fn stacks(&self) -> &[Stack] {
+        &self.stacks
+    }
+
+    /// Leaves the stacks alone if the move can't be done
+    pub fn apply(&mut self, crane: &dyn Crane, m: &Move) -> Result<(), String> {
+        let stack_count = self.stacks.len();
+        let describe = || format!("Line {}: move {} from {} to {}", m.line, m.count, m.from, m.to);
+        for stack in [m.from, m.to] {
+            if stack == 0 || stack > stack_count {
+                return Err(format!("{} - there's no stack {stack}", describe()));
+            }
+        }
+        let available = self.stacks[m.from - 1].len();
+        if available < m.count {
+            return Err(format!("{} - stack {} only has {available} crates", describe(), m.from));
+        }
+
+        if let Some(history) = &mut self.history {
+            history.undo.push(self.stacks.clone());
+            history.redo.clear();
+        }
+        if m.from != m.to {
+            let mut from = std::mem::take(&mut self.stacks[m.from - 1]);
+            crane.move_crates(&mut from, &mut self.stacks[m.to - 1], m.count);
+            self.stacks[m.from - 1] = from;
+        }
+        Ok(())
+    }
+
+    /// Goes back to how the stacks were before the last move, if the yard is keeping its history
+    pub fn undo(&mut self) -> bool {
+        let Some(history) = &mut self.history else { return false };
+        match history.undo.pop() {
+            Some(previous) => {
+                history
+                    .redo
+                    .push(std::mem::replace(&mut self.stacks, previous));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(history) = &mut self.history else { return false };
+        match history.redo.pop() {
+            Some(next) => {
+                history.undo.push(std::mem::replace(&mut self.stacks, next));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn tops(&self) -> String {
+        self.stacks
+            .iter()
+            .filter_map(|s| s.last().map(String::as_str))
+            .collect::<String>()
     }
 }
 
-fn evaluate(s: &str, perform_move: fn(stacks: &mut Stacks, m: &Move)) -> String {
-    let (mut stacks, moves) = parse(s);
+// The columns each stack number takes up, which can be more than one once there are ten or more stacks
+fn number_spans(line: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (column, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(column),
+            (Some(first), true) => {
+                spans.push((first, column - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+// Each crate belongs to the stack whose number is underneath it, so labels can be as wide as they like as long as
+// the numbers line up with them
+fn parse_stacks(s: &str) -> Result<Stacks, String> {
+    let mut line_iter = s.lines().rev();
+    let numbers = number_spans(line_iter.next().ok_or("No stacks")?);
+    let mut stacks = vec![Stack::new(); numbers.len()];
+    let line_count = s.lines().count();
+    for (line_index, line) in line_iter.enumerate() {
+        let line_number = line_count - line_index - 1;
+        let mut rest = line;
+        while let Some(start) = rest.find('[') {
+            let end = rest[start..]
+                .find(']')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("Line {line_number}: unclosed crate"))?;
+            let offset = line.len() - rest.len();
+            let stack = numbers
+                .iter()
+                .position(|(first, last)| *first <= offset + end && offset + start <= *last)
+                .ok_or_else(|| {
+                    format!("Line {line_number}: crate at column {} isn't over a stack", offset + start + 1)
+                })?;
+            stacks[stack].push(rest[start + 1..end].to_owned());
+            rest = &rest[end + 1..];
+        }
+    }
+    Ok(stacks)
+}
+
+fn parse_move(s: &str, line: usize) -> Result<Move, String> {
+    match s.split_whitespace().collect::<Vec<_>>()[..] {
+        ["move", count, "from", from, "to", to] => {
+            let number = |n: &str| {
+                n.parse::<usize>()
+                    .map_err(|e| format!("Line {line}: bad number {n} - {e}"))
+            };
+            Ok(Move { count: number(count)?, from: number(from)?, to: number(to)?, line })
+        }
+        _ => Err(format!("Line {line}: bad move '{s}'")),
+    }
+}
+
+pub fn parse(s: &str) -> Result<(Stacks, Moves), String> {
+    let s = s.replace("\r\n", "\n");
+    let (stacks, moves) = s
+        .split_once("\n\n")
+        .ok_or("No blank line between the stacks and the moves")?;
+    // Moves are numbered by where they are in the whole input
+    let first_move_line = stacks.lines().count() + 2;
+    let moves = moves
+        .lines()
+        .enumerate()
+        .map(|(index, line)| parse_move(line, first_move_line + index))
+        .collect::<Result<Moves, _>>()?;
+    Ok((parse_stacks(stacks)?, moves))
+}
+
+/// Runs all the moves in the input with any crane, giving the crates on top at the end
+pub fn rearrange(s: &str, crane: &dyn Crane) -> Result<String, String> {
+    let (stacks, moves) = parse(s)?;
+    let mut yard = Yard::new(stacks);
     for m in moves {
-        perform_move(&mut stacks, &m);
+        yard.apply(crane, &m)?;
     }
-    stacks
-        .into_iter()
-        .map(|s| *s.last().unwrap())
-        .collect::<String>()
+    Ok(yard.tops())
+}
+
+fn evaluate(s: &str, crane: &dyn Crane) -> String {
+    rearrange(s, crane).unwrap_or_else(|e| panic!("{e}"))
 }
 
 fn part1_evaluate(s: &str) -> String {
-    evaluate(s, perform_move_part1)
+    evaluate(s, &OneAtATime)
 }
 
 fn part2_evaluate(s: &str) -> String {
-    evaluate(s, perform_move_part2)
+    evaluate(s, &Batch)
 }
 
 #[cfg(test)]
@@ -99,16 +277,132 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), "CMZ");
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), "MCD");
 }
 
+#[cfg(test)]
+#[test]
+fn test_cranes() {
+    let cranes: [(&dyn Crane, &str); 5] = [
+        (&OneAtATime, "CMZ"),
+        (&Batch, "MCD"),
+        (&ReversedBatch, "CMZ"),
+        (&CapacityLimited::new(1).unwrap(), "CMZ"),
+        (&CapacityLimited::new(2).unwrap(), "MCZ"),
+    ];
+    for (crane, tops) in cranes {
+        assert_eq!(evaluate(TEST_INPUT_STRING, crane), tops);
+    }
+
+    let stack = |labels: &str| labels.split(' ').map(str::to_owned).collect::<Stack>();
+    let mut to = Stack::new();
+    CapacityLimited::new(2)
+        .unwrap()
+        .move_crates(&mut stack("A B C D E"), &mut to, 5);
+    assert_eq!(to, stack("D E B C A"));
+    assert!(CapacityLimited::new(0).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_validation() {
+    let (stacks, moves) = parse(TEST_INPUT_STRING).unwrap();
+    assert_eq!(moves[1], Move { count: 3, from: 1, to: 3, line: 7 });
+    let mut yard = Yard::new(stacks);
+    let bad = |count, from, to| Move { count, from, to, line: 99 };
+    assert_eq!(
+        yard.apply(&Batch, &bad(3, 3, 1)),
+        Err("Line 99: move 3 from 3 to 1 - stack 3 only has 1 crates".into())
+    );
+    assert_eq!(yard.apply(&Batch, &bad(1, 4, 1)), Err("Line 99: move 1 from 4 to 1 - there's no stack 4".into()));
+    assert_eq!(yard.apply(&Batch, &bad(1, 1, 0)), Err("Line 99: move 1 from 1 to 0 - there's no stack 0".into()));
+    assert!(!yard.undo());
+
+    let input = TEST_INPUT_STRING.replace("move 2 from 2 to 1", "move 2 from 2 to 1\nmove 5 from 2 to 1");
+    let (stacks, moves) = parse(&input).unwrap();
+    let mut yard = Yard::new(stacks);
+    let error = moves
+        .iter()
+        .try_for_each(|m| yard.apply(&OneAtATime, m))
+        .unwrap_err();
+    assert_eq!(error, "Line 9: move 5 from 2 to 1 - stack 2 only has 0 crates");
+    assert_eq!(rearrange(&input, &Batch), Err(error));
+    assert_eq!(parse("[A]\n 1\n\nmove 1 from 1\n").err(), Some("Line 4: bad move 'move 1 from 1'".into()));
+    assert_eq!(parse("[A]\n 1\n\nmove x from 1 to 1").err().unwrap()[..21], *"Line 4: bad number x ");
+}
+
+#[cfg(test)]
+#[test]
+fn test_history() {
+    let (stacks, moves) = parse(TEST_INPUT_STRING).unwrap();
+    let mut yard = Yard::with_history(stacks);
+    let tops = moves
+        .iter()
+        .map(|m| {
+            yard.apply(&OneAtATime, m).unwrap();
+            yard.tops()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(tops, ["DCP", "CZ", "MZ", "CMZ"]);
+    assert!(yard.undo() && yard.undo());
+    assert_eq!(yard.tops(), "CZ");
+    assert!(yard.redo());
+    assert_eq!(yard.tops(), "MZ");
+    assert!(yard.undo() && yard.undo() && yard.undo());
+    assert_eq!(yard.tops(), "NDP");
+    assert!(!yard.undo());
+
+    // A new move forgets anything that was undone
+    yard.apply(&Batch, &moves[3]).unwrap();
+    assert!(!yard.redo());
+    assert_eq!(yard.stacks()[1], ["M", "C", "D", "N"]);
+
+    // Only kept when asked for
+    let mut yard = Yard::new(parse(TEST_INPUT_STRING).unwrap().0);
+    yard.apply(&Batch, &moves[0]).unwrap();
+    assert!(!yard.undo());
+    assert_eq!(yard.tops(), "DCP");
+}
+
+#[cfg(test)]
+#[test]
+fn test_labels() {
+    let input = "\
+\x20    [BB]
+[A]  [CCC]    [D]
+ 1    2        3
+
+move 2 from 2 to 3";
+    let (stacks, _) = parse(input).unwrap();
+    assert_eq!(stacks, [vec!["A"], vec!["CCC", "BB"], vec!["D"]]);
+    assert_eq!(evaluate(input, &Batch), "ABB");
+    assert!(parse_stacks("[A] [B]\n 1").is_err());
+
+    // Stack numbers can be more than one digit wide
+    let labels = ('A'..='K').collect::<Vec<_>>();
+    let crates = labels.iter().map(|l| format!("[{l}]")).collect::<Vec<_>>();
+    let numbers = (1..=labels.len())
+        .map(|n| format!("{n:^3}"))
+        .collect::<Vec<_>>();
+    let input = format!("{}\n{}\n\nmove 1 from 11 to 10\nmove 1 from 10 to 2", crates.join(" "), numbers.join(" "));
+    let (stacks, _) = parse(&input).unwrap();
+    assert_eq!(stacks.len(), 11);
+    assert_eq!(stacks[9], ["J"]);
+    assert_eq!(evaluate(&input, &OneAtATime), "AKCDEFGHIJ");
+}
+
+pub fn solve(input_string: &str) -> (String, String) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(String, bool, String, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     let part1_good = part1_answer == "SHMSDGZVC";
-    let part2_answer = part2_evaluate(input_string);
     let part2_good = part2_answer == "VRZGHDFBQ";
     Some((part1_answer, part1_good, part2_answer, part2_good))
 }
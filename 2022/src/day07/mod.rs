@@ -1,30 +1,60 @@
+use super::profile;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// An entry in a directory: another directory, by its id, or a file and its size
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Node {
+    Dir(usize),
+    File(u64),
+}
+
 #[derive(Debug)]
 struct Dir {
-    file_size: u64,
-    dirs: Vec<usize>,
+    name: String,
     parent: usize,
+    // Directories and files share a namespace, and are kept sorted by name
+    entries: BTreeMap<String, Node>,
+    listed: bool,
 }
 
 const FILESYSTEM_ROOT_ID: usize = 0;
-const FILESYSTEM_SIZE: u64 = 70_000_000;
-const SIZE_NEEDED_FOR_UPDATE: u64 = 30_000_000;
+/// How big the disk is, and how much free space the update needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disk {
+    pub size: u64,
+    pub needed_for_update: u64,
+}
 
+impl Disk {
+    pub const PUZZLE: Disk = Disk { size: 70_000_000, needed_for_update: 30_000_000 };
+}
+
+/// The directory tree a terminal transcript explores.
 #[derive(Debug)]
-struct Filesystem {
+pub struct Filesystem {
     dirs: Vec<Dir>,
 }
 
 impl Filesystem {
     fn new() -> Self {
         let mut new_fs = Filesystem { dirs: vec![] };
-        new_fs.dirs.push(Dir::new(FILESYSTEM_ROOT_ID));
+        new_fs.dirs.push(Dir::new("/", FILESYSTEM_ROOT_ID));
         new_fs
     }
-    fn mkdir(&mut self, parent: usize) -> usize {
-        let new_dir_id = self.dirs.len();
-        self.dirs.push(Dir::new(parent));
-        self.get_dir_mut(parent).dirs.push(new_dir_id);
-        new_dir_id
+    fn mkdir(&mut self, parent: usize, name: &str) -> Result<usize, String> {
+        match self.get_dir(parent).entries.get(name) {
+            Some(Node::Dir(id)) => Ok(*id),
+            Some(Node::File(_)) => Err(format!("{name} is a file, not a directory")),
+            None => {
+                let new_dir_id = self.dirs.len();
+                self.dirs.push(Dir::new(name, parent));
+                self.get_dir_mut(parent)
+                    .entries
+                    .insert(name.to_owned(), Node::Dir(new_dir_id));
+                Ok(new_dir_id)
+            }
+        }
     }
     fn get_dir(&self, id: usize) -> &Dir {
         &self.dirs[id]
@@ -32,81 +62,183 @@ impl Filesystem {
     fn get_dir_mut(&mut self, id: usize) -> &mut Dir {
         &mut self.dirs[id]
     }
+    pub fn path(&self, id: usize) -> String {
+        if id == FILESYSTEM_ROOT_ID {
+            return "/".to_owned();
+        }
+        let mut names = vec![];
+        let mut id = id;
+        while id != FILESYSTEM_ROOT_ID {
+            names.push(self.get_dir(id).name.as_str());
+            id = self.get_dir(id).parent;
+        }
+        names.iter().rev().map(|name| format!("/{name}")).collect()
+    }
+    /// Paths are absolute, e.g. `/a/e` or `/b.txt`
+    pub fn lookup(&self, path: &str) -> Option<Node> {
+        path.strip_prefix('/')?
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(Node::Dir(FILESYSTEM_ROOT_ID), |node, name| match node {
+                Node::Dir(id) => self.get_dir(id).entries.get(name).copied(),
+                Node::File(_) => None,
+            })
+    }
+    /// The size of a file, or of everything in a directory
+    pub fn du(&self, path: &str) -> Option<u64> {
+        match self.lookup(path)? {
+            Node::Dir(id) => Some(self.get_dir(id).total_size(self)),
+            Node::File(size) => Some(size),
+        }
+    }
+    fn dir_sizes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.dirs.iter().map(|d| d.total_size(self))
+    }
+    fn render(&self, f: &mut std::fmt::Formatter<'_>, name: &str, node: Node, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        match node {
+            Node::File(size) => writeln!(f, "{indent}- {name} (file, size={size})"),
+            Node::Dir(id) => {
+                writeln!(f, "{indent}- {name} (dir)")?;
+                for (name, node) in &self.get_dir(id).entries {
+                    self.render(f, name, *node, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Renders like `tree`, in the same format as the puzzle text
+impl Display for Filesystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.render(f, "/", Node::Dir(FILESYSTEM_ROOT_ID), 0)
+    }
 }
 
 impl Dir {
-    fn new(parent: usize) -> Self {
-        Dir { file_size: 0, dirs: Vec::new(), parent }
+    fn new(name: &str, parent: usize) -> Self {
+        Dir { name: name.to_owned(), parent, entries: BTreeMap::new(), listed: false }
     }
     fn total_size(&self, fs: &Filesystem) -> u64 {
-        self.file_size
-            + self
-                .dirs
-                .iter()
-                .map(|d| fs.get_dir(*d).total_size(fs))
-                .sum::<u64>()
-    }
-    fn mkfile(&mut self, size: u64) {
-        self.file_size += size;
+        self.entries
+            .values()
+            .map(|node| match node {
+                Node::Dir(d) => fs.get_dir(*d).total_size(fs),
+                Node::File(size) => *size,
+            })
+            .sum::<u64>()
     }
 }
 
-fn parse(s: &str) -> Filesystem {
+// Builds the tree from a transcript. Directories are only created once however often they're visited, and a
+// directory that's listed more than once must have the same contents each time.
+pub fn parse(s: &str) -> Result<Filesystem, String> {
     let mut fs = Filesystem::new();
     let mut cwd = FILESYSTEM_ROOT_ID;
-    for l in s.lines() {
+    // The line number of the `ls` in progress, if there is one, and the entries it's output so far
+    let mut listing: Option<(usize, BTreeMap<String, Option<u64>>)> = None;
+    let finish_listing = |fs: &mut Filesystem, cwd: usize, listing: Option<(usize, BTreeMap<_, _>)>| {
+        let Some((ls_line_number, listing)) = listing else { return Ok(()) };
+        let error = |e: String| format!("line {ls_line_number}: {e}");
+        let dir = fs.get_dir_mut(cwd);
+        if dir.listed {
+            let previous = dir
+                .entries
+                .iter()
+                .map(|(name, node)| (name.clone(), if let Node::File(size) = node { Some(*size) } else { None }))
+                .collect::<BTreeMap<_, _>>();
+            if previous != listing {
+                return Err(error(format!("{} has different contents each time it's listed", fs.path(cwd))));
+            }
+            return Ok(());
+        }
+        dir.listed = true;
+        for (name, size) in listing {
+            match size {
+                Some(size) => {
+                    if let Some(Node::Dir(_)) = fs
+                        .get_dir_mut(cwd)
+                        .entries
+                        .insert(name.clone(), Node::File(size))
+                    {
+                        return Err(error(format!("{name} in {} is both a file and a directory", fs.path(cwd))));
+                    }
+                }
+                None => {
+                    fs.mkdir(cwd, &name).map_err(error)?;
+                }
+            }
+        }
+        Ok(())
+    };
+
+    for (line_number, l) in s.lines().enumerate() {
+        let line_number = line_number + 1;
+        let error = |e: String| format!("line {line_number}: {e}");
         let mut tokens = l.split_whitespace();
         match tokens.next() {
             None => continue, // Empty line - go to next line
             Some("$") => {
                 // Command
+                finish_listing(&mut fs, cwd, listing.take())?;
                 match tokens.next() {
-                    Some("ls") => continue,
+                    Some("ls") => listing = Some((line_number, BTreeMap::new())),
                     Some("cd") => match tokens.next() {
                         Some("/") => cwd = FILESYSTEM_ROOT_ID,
+                        Some("..") if cwd == FILESYSTEM_ROOT_ID => return Err(error("cd .. from /".to_owned())),
                         Some("..") => cwd = fs.get_dir(cwd).parent,
-                        Some(_) => cwd = fs.mkdir(cwd),
-                        None => panic!("Expect directory name in {l}"),
+                        Some(name) => {
+                            if fs.get_dir(cwd).listed && !fs.get_dir(cwd).entries.contains_key(name) {
+                                return Err(error(format!("{name} isn't in the listing of {}", fs.path(cwd))));
+                            }
+                            cwd = fs.mkdir(cwd, name).map_err(error)?
+                        }
+                        None => return Err(error(format!("Expect directory name in {l}"))),
                     },
-                    Some(_) => panic!("Bad command in {l}"),
-                    None => panic!("Expect command in {l}"),
+                    Some(_) => return Err(error(format!("Bad command in {l}"))),
+                    None => return Err(error(format!("Expect command in {l}"))),
                 }
             }
-            Some("dir") => {}
-            Some(maybe_size) => {
-                // File entry
-                match maybe_size.parse::<u64>() {
-                    Ok(size) => fs.get_dir_mut(cwd).mkfile(size),
-                    _ => panic!("Bad input line {l}"),
+            Some(first) => {
+                let Some((_, entries)) = listing.as_mut() else {
+                    return Err(error(format!("Output without a command in {l}")));
+                };
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| error(format!("Expect name in {l}")))?;
+                let size = match first {
+                    "dir" => None,
+                    maybe_size => Some(
+                        maybe_size
+                            .parse::<u64>()
+                            .map_err(|_| error(format!("Bad input line {l}")))?,
+                    ),
+                };
+                if entries.insert(name.to_owned(), size).is_some() {
+                    return Err(error(format!("{name} listed twice")));
                 }
             }
         }
     }
-    fs
+    finish_listing(&mut fs, cwd, listing.take())?;
+    Ok(fs)
 }
 
 fn part1_evaluate(fs: &Filesystem) -> u64 {
-    fs.dirs
-        .iter()
-        .filter_map(|d| {
-            let this_size = d.total_size(fs);
-            (this_size <= 100_000).then_some(this_size)
-        })
-        .sum::<u64>()
+    fs.dir_sizes().filter(|size| *size <= 100_000).sum::<u64>()
 }
 
-fn part2_evaluate(fs: &Filesystem) -> u64 {
+// The size of the smallest directory that would free enough space for the update, if there is one
+fn smallest_deletion(fs: &Filesystem, disk: &Disk) -> Option<u64> {
     let space_used = fs.get_dir(FILESYSTEM_ROOT_ID).total_size(fs);
-    let space_available = FILESYSTEM_SIZE - space_used;
-    let space_to_free = SIZE_NEEDED_FOR_UPDATE.max(space_available) - space_available;
-    fs.dirs
-        .iter()
-        .filter_map(|d| {
-            let this_size = d.total_size(fs);
-            (this_size >= space_to_free).then_some(this_size)
-        })
-        .min()
-        .expect("Minimum directory size found")
+    let space_available = disk.size.checked_sub(space_used)?;
+    let space_to_free = disk.needed_for_update.saturating_sub(space_available);
+    fs.dir_sizes().filter(|size| *size >= space_to_free).min()
+}
+
+fn part2_evaluate(fs: &Filesystem, disk: &Disk) -> u64 {
+    smallest_deletion(fs, disk).expect("Minimum directory size found")
 }
 
 #[cfg(test)]
@@ -137,20 +269,85 @@ $ ls
 #[cfg(test)]
 #[test]
 fn test_part1() {
-    let fs = parse(TEST_INPUT_STRING);
+    let fs = parse(TEST_INPUT_STRING).unwrap();
     assert_eq!(part1_evaluate(&fs), 95_437);
 }
 
+#[cfg(test)]
+#[test]
+fn test_part2() {
+    let fs = parse(TEST_INPUT_STRING).unwrap();
+    assert_eq!(part2_evaluate(&fs, &Disk::PUZZLE), 24_933_642);
+    let disk = |size, needed_for_update| Disk { size, needed_for_update };
+    assert_eq!(smallest_deletion(&fs, &disk(50_000_000, 30_000_000)), Some(48_381_165));
+    assert_eq!(smallest_deletion(&fs, &disk(70_000_000, 20_000_000)), Some(584));
+    assert_eq!(smallest_deletion(&fs, &disk(40_000_000, 30_000_000)), None);
+    assert_eq!(solve_with(TEST_INPUT_STRING, disk(70_000_000, 20_000_000)), (95_437, 584));
+}
+
+#[cfg(test)]
+#[test]
+fn test_tree() {
+    let fs = parse(TEST_INPUT_STRING).unwrap();
+    assert_eq!(
+        fs.to_string(),
+        "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+    );
+    assert_eq!(fs.du("/a"), Some(94_853));
+    assert_eq!(fs.du("/a/e"), Some(584));
+    assert_eq!(fs.du("/d/k"), Some(7_214_296));
+    assert_eq!(fs.du("/"), Some(48_381_165));
+    assert_eq!(fs.du("/x"), None);
+    assert_eq!(fs.du("/b.txt/x"), None);
+    let Some(Node::Dir(e)) = fs.lookup("/a/e") else { panic!("/a/e isn't a directory") };
+    assert_eq!(fs.path(e), "/a/e");
+}
+
+#[cfg(test)]
 #[test]
-fn test_part2() {   
-    let fs = parse(TEST_INPUT_STRING);
-    assert_eq!(part2_evaluate(&fs), 24_933_642);
+fn test_revisits() {
+    // Going back into a directory and listing it again changes nothing
+    let revisited = format!("{TEST_INPUT_STRING}\n$ cd /\n$ cd a\n$ ls\ndir e\n29116 f\n2557 g\n62596 h.lst\n$ cd e");
+    let fs = parse(&revisited).unwrap();
+    assert_eq!(fs.dirs.len(), 4);
+    assert_eq!(part1_evaluate(&fs), 95_437);
+
+    let changed = format!("{TEST_INPUT_STRING}\n$ cd /\n$ cd a\n$ ls\ndir e\n29117 f\n2557 g\n62596 h.lst");
+    assert_eq!(parse(&changed).unwrap_err(), "line 26: /a has different contents each time it's listed");
+    let missing = format!("{TEST_INPUT_STRING}\n$ cd /\n$ cd x");
+    assert_eq!(parse(&missing).unwrap_err(), "line 25: x isn't in the listing of /");
+    assert!(parse("$ cd ..").is_err());
+    assert!(parse("$ ls\n1 a\ndir a").is_err());
+}
+
+pub fn solve(input_string: &str) -> (u64, u64) {
+    solve_with(input_string, Disk::PUZZLE)
+}
+
+/// Solves for a disk of a different size, or an update needing a different amount of space
+pub fn solve_with(input_string: &str, disk: Disk) -> (u64, u64) {
+    let parse = || parse(input_string).unwrap_or_else(|e| panic!("{e}"));
+    let part1_answer = profile::part("part 1", || part1_evaluate(&parse()));
+    let part2_answer = profile::part("part 2", || part2_evaluate(&parse(), &disk));
+    (part1_answer, part2_answer)
 }
 
 pub fn run() -> Option<(u64, bool, u64, bool)> {
-    let input_string = include_str!("./input.txt");
-    let fs = parse(input_string);
-    let part1_answer = part1_evaluate(&fs);
-    let part2_answer = part2_evaluate(&fs);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 1_648_397, part2_answer, part2_answer == 1_815_525))
 }
@@ -1,76 +1,35 @@
-use itertools::Itertools;
+mod visibility;
+
+use super::profile;
 use ndarray::Array2;
+pub use visibility::{Direction, View, Visibility, DIAGONAL, ORTHOGONAL};
 
-fn parse(s: &str) -> Array2<u8> {
-    let mut row_size = Option::None;
+pub fn parse(s: &str) -> Array2<u8> {
+    let ncols = s.lines().next().map_or(0, str::len);
     let numbers = s
         .lines()
-        .flat_map(|l| {
-            if row_size.is_none() {
-                row_size = Some(l.as_bytes().len());
-            }
-            l.bytes()
-                .map(|digit_char| digit_char - b'0')
-                .collect::<Vec<_>>()
-        })
+        .flat_map(|l| l.bytes().map(|digit_char| digit_char - b'0'))
         .collect::<Vec<_>>();
-    Array2::from_shape_vec((row_size.unwrap(), numbers.len() / row_size.unwrap()), numbers)
-        .expect("Bad vector -> array2 construction")
+    Array2::from_shape_vec((numbers.len() / ncols, ncols), numbers).expect("Bad vector -> array2 construction")
 }
 
-fn part1_evaluate(s: &str) -> usize {
-    let arr = parse(s);
+/// Every tree's visibility and viewing distances in each of the directions, for rendering or querying
+pub fn analyse(s: &str, directions: &[Direction]) -> Visibility {
+    Visibility::new(&parse(s), directions)
+}
 
-    (0..arr.nrows())
-        .cartesian_product(0..arr.ncols())
-        .filter(|(r, c)| {
-            if *r == 0 || *r == (arr.nrows() - 1) || *c == 0 || *c == (arr.ncols() - 1) {
-                return true;
-            }
-            let this_val = arr.get((*r, *c)).unwrap();
-            (0..*c).all(|c| arr.get((*r, c)).unwrap() < this_val)
-                || (c + 1..arr.ncols()).all(|c| arr.get((*r, c)).unwrap() < this_val)
-                || (0..*r).all(|r| arr.get((r, *c)).unwrap() < this_val)
-                || (r + 1..arr.nrows()).all(|r| arr.get((r, *c)).unwrap() < this_val)
-        })
+fn part1_evaluate(s: &str) -> usize {
+    analyse(s, &ORTHOGONAL)
+        .visible()
+        .iter()
+        .filter(|v| **v)
         .count()
 }
 
 fn part2_evaluate(s: &str) -> usize {
-    let arr = parse(s);
-
-    (0..arr.nrows())
-        .cartesian_product(0..arr.ncols())
-        .map(|(r, c)| {
-            if r == 0 || r == (arr.nrows() - 1) || c == 0 || c == (arr.ncols() - 1) {
-                0
-            } else {
-                let this_val = arr.get((r, c)).unwrap();
-                let score_left = (1
-                    + (0..c)
-                        .rev()
-                        .take_while(|c| arr.get((r, *c)).unwrap() < this_val)
-                        .count())
-                .min((0..c).len());
-                let score_right = (1
-                    + (c + 1..arr.ncols())
-                        .take_while(|c| arr.get((r, *c)).unwrap() < this_val)
-                        .count())
-                .min((c + 1..arr.ncols()).len());
-                let score_up = (1
-                    + (0..r)
-                        .rev()
-                        .take_while(|r| arr.get((*r, c)).unwrap() < this_val)
-                        .count())
-                .min((0..r).len());
-                let score_down = (1
-                    + (r + 1..arr.nrows())
-                        .take_while(|r| arr.get((*r, c)).unwrap() < this_val)
-                        .count())
-                .min((r + 1..arr.nrows()).len());
-                score_down * score_left * score_right * score_up
-            }
-        })
+    *analyse(s, &ORTHOGONAL)
+        .scenic_scores()
+        .iter()
         .max()
         .unwrap()
 }
@@ -88,14 +47,100 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 21);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 8);
 }
 
+#[cfg(test)]
+#[test]
+fn test_visibility() {
+    let heights = parse(TEST_INPUT_STRING);
+    let visibility = Visibility::new(&heights, &ORTHOGONAL);
+    assert_eq!(visibility.render(&heights), "30373\n255.2\n65.32\n3.5.9\n35390");
+    // The puzzle's two examples: the middle 5 in the second row, and the 5 in the fourth
+    let distances = |p: (usize, usize)| {
+        visibility
+            .views
+            .iter()
+            .map(|v| v.distance[p])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(distances((1, 2)), [1, 2, 1, 2]);
+    assert_eq!(distances((3, 2)), [2, 1, 2, 2]);
+    assert_eq!(visibility.scenic_scores()[(3, 2)], 8);
+
+    let diagonal = Visibility::new(&heights, &DIAGONAL);
+    assert_eq!(diagonal.render(&heights), "30373\n255.2\n65.32\n3.549\n35390");
+    assert_eq!(diagonal.scenic_scores()[(2, 2)], 2);
+    let everywhere = [ORTHOGONAL, DIAGONAL].concat();
+    assert_eq!(
+        Visibility::new(&heights, &everywhere)
+            .visible()
+            .iter()
+            .filter(|v| **v)
+            .count(),
+        22
+    );
+
+    // Grids needn't be square
+    let heights = parse("123\n456");
+    let up = Visibility::new(&heights, &[Direction { rows: -1, cols: 0 }]);
+    assert_eq!(up.views[0].distance, ndarray::arr2(&[[0, 0, 0], [1, 1, 1]]));
+    assert!(up.visible().iter().all(|v| *v));
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 1_835, part2_answer, part2_answer == 263_670))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Looking straight along the line from each tree, the slow way
+    fn brute_force(heights: &Array2<u8>, d: Direction) -> (Array2<bool>, Array2<usize>) {
+        let mut visible = Array2::from_elem(heights.dim(), false);
+        let mut distance = Array2::zeros(heights.dim());
+        for ((r, c), h) in heights.indexed_iter() {
+            let line = (1..)
+                .map(|n| (r as isize + n * d.rows, c as isize + n * d.cols))
+                .take_while(|(r, c)| *r >= 0 && *c >= 0)
+                .map_while(|(r, c)| heights.get((r as usize, c as usize)))
+                .collect::<Vec<_>>();
+            visible[(r, c)] = line.iter().all(|other| *other < h);
+            distance[(r, c)] = line
+                .iter()
+                .position(|other| *other >= h)
+                .map_or(line.len(), |n| n + 1);
+        }
+        (visible, distance)
+    }
+
+    proptest! {
+        #[test]
+        fn views_match_brute_force(
+            (rows, cols, cells) in (1usize..8, 1usize..8)
+                .prop_flat_map(|(rows, cols)| (Just(rows), Just(cols), prop::collection::vec(0u8..10, rows * cols))),
+            step in prop::sample::select(vec![(2isize, 1isize), (-1, 3), (0, 2)]),
+        ) {
+            let heights = Array2::from_shape_vec((rows, cols), cells).unwrap();
+            let knight = Direction { rows: step.0, cols: step.1 };
+            for d in ORTHOGONAL.into_iter().chain(DIAGONAL).chain([knight]) {
+                let view = View::new(&heights, d);
+                let (visible, distance) = brute_force(&heights, d);
+                prop_assert_eq!(view.visible, visible);
+                prop_assert_eq!(view.distance, distance);
+            }
+        }
+    }
+}
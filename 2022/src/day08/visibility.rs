@@ -0,0 +1,132 @@
+//! How far each tree can see, and whether it can be seen from outside the grid, looking along any set of directions.
+//! Each direction is handled in one pass over the grid: every line of trees running that way is walked from the edge
+//! the trees are looking towards, keeping a stack of the trees passed so far that nothing nearer has hidden yet.
+//! Those get taller towards the bottom of the stack, so popping everything shorter than a tree leaves the tree that
+//! blocks its view on top, and an empty stack means it can see all the way out.
+
+use ndarray::Array2;
+
+/// A step from one tree to the next, in rows and columns. Any step other than staying put will do, diagonals included.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Direction {
+    pub rows: isize,
+    pub cols: isize,
+}
+
+pub const ORTHOGONAL: [Direction; 4] = [
+    Direction { rows: -1, cols: 0 },
+    Direction { rows: 1, cols: 0 },
+    Direction { rows: 0, cols: -1 },
+    Direction { rows: 0, cols: 1 },
+];
+
+pub const DIAGONAL: [Direction; 4] = [
+    Direction { rows: -1, cols: -1 },
+    Direction { rows: -1, cols: 1 },
+    Direction { rows: 1, cols: -1 },
+    Direction { rows: 1, cols: 1 },
+];
+
+impl Direction {
+    fn step(&self, (r, c): (usize, usize), shape: (usize, usize)) -> Option<(usize, usize)> {
+        let r = r.checked_add_signed(self.rows).filter(|r| *r < shape.0)?;
+        let c = c.checked_add_signed(self.cols).filter(|c| *c < shape.1)?;
+        Some((r, c))
+    }
+
+    fn back(&self) -> Direction {
+        Direction { rows: -self.rows, cols: -self.cols }
+    }
+}
+
+/// What every tree sees looking one way.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct View {
+    pub direction: Direction,
+    /// Whether nothing as tall as the tree stands between it and the edge
+    pub visible: Array2<bool>,
+    /// How many trees it can see, up to and including the first one at least as tall as itself
+    pub distance: Array2<usize>,
+}
+
+impl View {
+    pub fn new(heights: &Array2<u8>, direction: Direction) -> Self {
+        assert!(direction != Direction { rows: 0, cols: 0 }, "Can't look without a direction");
+        let shape = heights.dim();
+        let mut visible = Array2::from_elem(shape, false);
+        let mut distance = Array2::zeros(shape);
+        let back = direction.back();
+        let mut stack: Vec<(u8, usize)> = Vec::new();
+        // The lines start from the trees with nothing further on in the direction they're looking
+        for start in heights
+            .indexed_iter()
+            .map(|(p, _)| p)
+            .filter(|p| direction.step(*p, shape).is_none())
+        {
+            stack.clear();
+            let mut here = Some(start);
+            let mut from_edge = 0;
+            while let Some(p) = here {
+                let height = heights[p];
+                while stack.last().is_some_and(|(h, _)| *h < height) {
+                    stack.pop();
+                }
+                match stack.last() {
+                    Some((_, blocker)) => distance[p] = from_edge - blocker,
+                    None => {
+                        visible[p] = true;
+                        distance[p] = from_edge;
+                    }
+                }
+                stack.push((height, from_edge));
+                here = back.step(p, shape);
+                from_edge += 1;
+            }
+        }
+        View { direction, visible, distance }
+    }
+}
+
+/// The views in each of a set of directions, and what they add up to.
+#[derive(Debug, Clone)]
+pub struct Visibility {
+    pub views: Vec<View>,
+}
+
+impl Visibility {
+    pub fn new(heights: &Array2<u8>, directions: &[Direction]) -> Self {
+        assert!(!directions.is_empty(), "Need at least one direction to look in");
+        Visibility { views: directions.iter().map(|d| View::new(heights, *d)).collect() }
+    }
+
+    /// Whether each tree can be seen from outside the grid in any of the directions
+    pub fn visible(&self) -> Array2<bool> {
+        let mut views = self.views.iter();
+        let first = views.next().unwrap().visible.clone();
+        views.fold(first, |acc, view| acc | &view.visible)
+    }
+
+    /// Each tree's viewing distances multiplied together
+    pub fn scenic_scores(&self) -> Array2<usize> {
+        let mut views = self.views.iter();
+        let first = views.next().unwrap().distance.clone();
+        views.fold(first, |acc, view| acc * &view.distance)
+    }
+
+    /// The trees that can be seen from outside the grid, with `.` for those that can't
+    pub fn render(&self, heights: &Array2<u8>) -> String {
+        let visible = self.visible();
+        heights
+            .rows()
+            .into_iter()
+            .zip(visible.rows())
+            .map(|(row, visible)| {
+                row.iter()
+                    .zip(visible.iter())
+                    .map(|(h, v)| if *v { (b'0' + h) as char } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -1,105 +1,207 @@
+use super::profile;
 use std::collections::HashSet;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Default)]
 struct Coord {
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
+    z: i32,
 }
 
+// The puzzle only goes up, down, left and right, but ropes can go forwards and backwards as well
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
 enum Direction {
     Up,
     Down,
     Left,
     Right,
+    Forward,
+    Back,
 }
 
-impl From<char> for Direction {
-    fn from(c: char) -> Direction {
+impl TryFrom<char> for Direction {
+    type Error = String;
+
+    fn try_from(c: char) -> Result<Direction, Self::Error> {
         match c {
-            'U' => Direction::Up,
-            'D' => Direction::Down,
-            'L' => Direction::Left,
-            'R' => Direction::Right,
-            _ => panic!("Bad direction character"),
+            'U' => Ok(Direction::Up),
+            'D' => Ok(Direction::Down),
+            'L' => Ok(Direction::Left),
+            'R' => Ok(Direction::Right),
+            'F' => Ok(Direction::Forward),
+            'B' => Ok(Direction::Back),
+            _ => Err(format!("Bad direction character {c}")),
         }
     }
 }
 
 impl Coord {
-    fn origin() -> Self {
-        Self::new(u32::MAX / 2, u32::MAX / 2)
-    }
-    fn new(x: u32, y: u32) -> Self {
-        Self { x, y }
-    }
     fn move_(self, d: Direction) -> Self {
         match d {
             Direction::Up => Coord { y: self.y + 1, ..self },
             Direction::Down => Coord { y: self.y - 1, ..self },
             Direction::Left => Coord { x: self.x - 1, ..self },
             Direction::Right => Coord { x: self.x + 1, ..self },
+            Direction::Forward => Coord { z: self.z + 1, ..self },
+            Direction::Back => Coord { z: self.z - 1, ..self },
         }
     }
-    fn offset(self, other: Coord) -> [(Direction, u32); 2] {
-        let x_offset = (if self.x > other.x { Direction::Left } else { Direction::Right }, self.x.abs_diff(other.x));
-        let y_offset = (if self.y > other.y { Direction::Down } else { Direction::Up }, self.y.abs_diff(other.y));
-        [x_offset, y_offset]
-    }
-    fn packed(self) -> u64 {
-        ((self.x as u64) << 32) | (self.y as u64)
+    // A knot only moves once it's no longer touching the one in front, and then it moves one step towards it along
+    // every axis they differ on
+    fn follow(self, leader: Coord) -> Self {
+        let (dx, dy, dz) = (leader.x - self.x, leader.y - self.y, leader.z - self.z);
+        if dx.abs() <= 1 && dy.abs() <= 1 && dz.abs() <= 1 {
+            return self;
+        }
+        Coord { x: self.x + dx.signum(), y: self.y + dy.signum(), z: self.z + dz.signum() }
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
-struct Rope<const N: usize> {
-    knots: [Coord; N],
+// Which knots to remember the whereabouts of
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Tracking {
+    Tail,
+    EveryKnot,
+}
+
+#[derive(Clone, Debug)]
+struct Rope {
+    knots: Vec<Coord>,
+    tracking: Tracking,
+    // Everywhere each tracked knot has been
+    visited: Vec<HashSet<Coord>>,
 }
 
-impl<const N: usize> Rope<N> {
-    fn new(pt: Coord) -> Self {
-        Self { knots: [pt; N] }
+impl Rope {
+    fn new(knot_count: usize, start: Coord, tracking: Tracking) -> Self {
+        assert!(knot_count > 0, "A rope needs at least one knot");
+        let tracked = match tracking {
+            Tracking::Tail => 1,
+            Tracking::EveryKnot => knot_count,
+        };
+        Self { knots: vec![start; knot_count], tracking, visited: vec![HashSet::from([start]); tracked] }
     }
     fn move_(&mut self, d: Direction) {
         self.knots[0] = self.knots[0].move_(d);
-        for n in 1..N {
-            let offset = self.knots[n].offset(self.knots[n - 1]);
-            self.knots[n] = if offset[0].1 >= 2 && offset[1].1 == 0 {
-                self.knots[n].move_(offset[0].0)
-            } else if offset[1].1 >= 2 && offset[0].1 == 0 {
-                self.knots[n].move_(offset[1].0)
-            } else if (offset[0].1 >= 2 && offset[1].1 >= 1) || (offset[0].1 >= 1 && offset[1].1 >= 2) {
-                self.knots[n].move_(offset[0].0).move_(offset[1].0)
-            } else {
-                self.knots[n]
+        for n in 1..self.knots.len() {
+            self.knots[n] = self.knots[n].follow(self.knots[n - 1]);
+        }
+        match self.tracking {
+            Tracking::Tail => {
+                self.visited[0].insert(*self.knots.last().unwrap());
+            }
+            Tracking::EveryKnot => {
+                for (visited, knot) in self.visited.iter_mut().zip(&self.knots) {
+                    visited.insert(*knot);
+                }
             }
         }
     }
+    // Everywhere a knot has been, if the rope's been keeping track of it
+    fn visited(&self, knot: usize) -> Option<&HashSet<Coord>> {
+        match self.tracking {
+            Tracking::Tail if knot + 1 == self.knots.len() => self.visited.first(),
+            Tracking::Tail => None,
+            Tracking::EveryKnot => self.visited.get(knot),
+        }
+    }
+    fn tail_visited(&self) -> &HashSet<Coord> {
+        self.visited.last().unwrap()
+    }
+
+    // Draws the rows from `max` down to `min` the way the puzzle does, looking down on the x-y plane. Each knot is
+    // shown by its number, with the head as `H` (and the tail of a two knot rope as `T`), and where a knot covers
+    // another only the one nearer the head shows.
+    #[cfg(test)]
+    fn render(&self, start: Coord, min: (i32, i32), max: (i32, i32)) -> String {
+        let label = |n: usize| match n {
+            0 => 'H',
+            1 if self.knots.len() == 2 => 'T',
+            n => char::from_digit(n as u32, 36).unwrap_or('#'),
+        };
+        self.draw(min, max, |c| {
+            self.knots
+                .iter()
+                .position(|k| (k.x, k.y) == c)
+                .map(label)
+                .or_else(|| ((start.x, start.y) == c).then_some('s'))
+        })
+    }
+
+    // Like the puzzle's drawing of everywhere the tail went, but for any tracked knot
+    #[cfg(test)]
+    fn render_visited(&self, knot: usize, start: Coord, min: (i32, i32), max: (i32, i32)) -> String {
+        let visited = self
+            .visited(knot)
+            .unwrap_or_else(|| panic!("Knot {knot} isn't being tracked"))
+            .iter()
+            .map(|k| (k.x, k.y))
+            .collect::<HashSet<_>>();
+        self.draw(min, max, |c| if (start.x, start.y) == c { Some('s') } else { visited.contains(&c).then_some('#') })
+    }
+
+    #[cfg(test)]
+    fn draw(&self, min: (i32, i32), max: (i32, i32), cell: impl Fn((i32, i32)) -> Option<char>) -> String {
+        (min.1..=max.1)
+            .rev()
+            .map(|y| {
+                (min.0..=max.0)
+                    .map(|x| cell((x, y)).unwrap_or('.'))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn parse(s: &str) -> Result<Vec<(Direction, usize)>, String> {
+    s.lines()
+        .map(|l| {
+            let (dir, count) = l
+                .split_once(' ')
+                .ok_or_else(|| format!("Bad input line {l}"))?;
+            let count = str::parse::<usize>(count).map_err(|_| format!("Bad count in line {l}"))?;
+            let mut chars = dir.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok((Direction::try_from(c)?, count)),
+                _ => Err(format!("Bad direction in line {l}")),
+            }
+        })
+        .collect()
 }
-fn evaluate<const N: usize>(s: &str) -> usize {
-    let mut tail_points = HashSet::new();
-    let mut r = Rope::<N>::new(Coord::origin());
-    tail_points.insert(r.knots.last().unwrap().packed());
-    s.lines().for_each(|l| {
-        let (dir, count) = l
-            .split_once(' ')
-            .unwrap_or_else(|| panic!("Bad input line {l}"));
-        let count = str::parse::<u32>(count).unwrap_or_else(|_| panic!("Bad count in line {l}"));
-        let dir = Direction::from(dir.chars().next().unwrap());
-        for _ in 0..count {
-            r.move_(dir);
-            tail_points.insert(r.knots.last().unwrap().packed());
+
+fn run_moves(moves: &[(Direction, usize)], knot_count: usize, tracking: Tracking) -> Rope {
+    let mut r = Rope::new(knot_count, Coord::default(), tracking);
+    for (dir, count) in moves {
+        for _ in 0..*count {
+            r.move_(*dir);
         }
-    });
-    tail_points.len()
+    }
+    r
+}
+
+fn simulate(s: &str, knot_count: usize, tracking: Tracking) -> Rope {
+    run_moves(&parse(s).unwrap_or_else(|e| panic!("{e}")), knot_count, tracking)
+}
+
+/// How many places each knot of a rope `knot_count` knots long visits, head first. The moves can go forwards and
+/// backwards (`F` and `B`) as well as up, down, left and right.
+pub fn knot_visits(s: &str, knot_count: usize) -> Result<Vec<usize>, String> {
+    if knot_count == 0 {
+        return Err("A rope needs at least one knot".to_owned());
+    }
+    let rope = run_moves(&parse(s)?, knot_count, Tracking::EveryKnot);
+    Ok((0..knot_count)
+        .map(|knot| rope.visited(knot).map_or(0, HashSet::len))
+        .collect())
 }
 
 fn part1_evaluate(s: &str) -> usize {
-    evaluate::<2>(s)
+    simulate(s, 2, Tracking::Tail).tail_visited().len()
 }
 
 fn part2_evaluate(s: &str) -> usize {
-    evaluate::<10>(s)
+    simulate(s, 10, Tracking::Tail).tail_visited().len()
 }
 
 #[cfg(test)]
@@ -128,15 +230,90 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING_1), 13);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING_1), 1);
     assert_eq!(part2_evaluate(TEST_INPUT_STRING_2), 36);
 }
 
+#[cfg(test)]
+#[test]
+fn test_render() {
+    let start = Coord::default();
+    let (min, max) = ((0, 0), (5, 4));
+    let mut rope = Rope::new(2, start, Tracking::Tail);
+    for _ in 0..4 {
+        rope.move_(Direction::Right);
+    }
+    assert_eq!(rope.render(start, min, max), "......\n......\n......\n......\ns..TH.");
+    for _ in 0..4 {
+        rope.move_(Direction::Up);
+    }
+    assert_eq!(rope.render(start, min, max), "....H.\n....T.\n......\n......\ns.....");
+
+    let rope = simulate(TEST_INPUT_STRING_1, 2, Tracking::Tail);
+    assert_eq!(rope.render_visited(1, start, min, max), "..##..\n...##.\n.####.\n....#.\ns###..");
+
+    let (min, max) = ((-11, -5), (14, 15));
+    let rope = simulate("R 5", 10, Tracking::Tail);
+    let mut expected = vec![".........................."; 21];
+    expected[15] = "...........54321H.........";
+    assert_eq!(rope.render(start, min, max), expected.join("\n"));
+
+    let rope = simulate(TEST_INPUT_STRING_2, 10, Tracking::EveryKnot);
+    let expected = "\
+..........................
+..........................
+..........................
+..........................
+..........................
+..........................
+..........................
+..........................
+..........................
+#.........................
+#.............###.........
+#............#...#........
+.#..........#.....#.......
+..#..........#.....#......
+...#........#.......#.....
+....#......s.........#....
+.....#..............#.....
+......#............#......
+.......#..........#.......
+........#........#........
+.........########.........";
+    assert_eq!(rope.render_visited(9, start, min, max), expected);
+    // The knots further up the rope go further
+    let visited = rope.visited.iter().map(HashSet::len).collect::<Vec<_>>();
+    assert!(visited.windows(2).all(|w| w[0] >= w[1]), "{visited:?}");
+}
+
+#[cfg(test)]
+#[test]
+fn test_3d() {
+    let rope = simulate("F 3\nU 2\nB 1\nR 2", 3, Tracking::EveryKnot);
+    assert_eq!(rope.knots, [Coord { x: 2, y: 2, z: 2 }, Coord { x: 1, y: 2, z: 2 }, Coord { x: 0, y: 1, z: 2 }]);
+    assert_eq!(rope.tail_visited().len(), 3);
+    assert_eq!(rope.visited(0).map(HashSet::len), Some(9));
+    let tail_only = simulate("F 3\nU 2\nB 1\nR 2", 3, Tracking::Tail);
+    assert_eq!(tail_only.visited(2), rope.visited(2));
+    assert_eq!(tail_only.visited(0), None);
+    assert_eq!(knot_visits("F 3\nU 2\nB 1\nR 2", 3), Ok(vec![9, 5, 3]));
+    assert!(knot_visits("U 1", 0).is_err());
+    assert!(parse("X 1").is_err());
+    assert!(parse("U x").is_err());
+    assert!(parse("UD 1").is_err());
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 6197, part2_answer, part2_answer == 2562))
 }
@@ -1,83 +1,265 @@
-#[allow(clippy::upper_case_acronyms)]
-struct CPU {
-    x: isize,
-    cycles: isize,
+use super::profile;
+use std::fmt::Display;
+use std::ops::ControlFlow;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Noop,
+    Addx(isize),
 }
 
-impl CPU {
-    fn new() -> Self {
-        Self { x: 1, cycles: 0 }
+impl Instruction {
+    pub fn cycles(self) -> usize {
+        match self {
+            Instruction::Noop => 1,
+            Instruction::Addx(_) => 2,
+        }
     }
-    fn addx(&mut self, delta: isize) {
-        self.x += delta;
-        self.cycles += 2;
+}
+
+impl FromStr for Instruction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["noop"] => Ok(Instruction::Noop),
+            ["addx", delta] => delta
+                .parse::<isize>()
+                .map(Instruction::Addx)
+                .map_err(|_| format!("Bad operand in {s}")),
+            _ => Err(format!("Bad instruction {s}")),
+        }
     }
-    fn noop(&mut self) {
-        self.cycles += 1;
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Noop => write!(f, "noop"),
+            Instruction::Addx(delta) => write!(f, "addx {delta}"),
+        }
     }
 }
-fn part1_evaluate(s: &str) -> isize {
-    s.lines()
-        .into_iter()
-        .scan(CPU::new(), |cpu, line| {
-            let cycles_before = cpu.cycles;
-            let x_before = cpu.x;
-            match line.split_once(' ') {
-                Some(("addx", delta)) => cpu.addx(
-                    delta
-                        .parse::<isize>()
-                        .unwrap_or_else(|_| panic!("Bad operand in {line}")),
-                ),
-                None if line == "noop" => cpu.noop(),
-                _ => panic!("Bad line {line}"),
-            };
-            Some((x_before, cycles_before, cpu.cycles))
-        })
-        .filter_map(|(x, cycles_before, cycles_after)| {
-            ((cycles_after + 20) / 40 != (cycles_before + 20) / 40)
-                .then_some(x * ((((cycles_after + 20) / 40) * 40) - 20))
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program(pub Vec<Instruction>);
+
+// The assembler: one instruction per line, ignoring blank lines and anything after a `;`
+impl FromStr for Program {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .map(|(number, line)| (number, line.split(';').next().unwrap().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(number, line)| Instruction::from_str(line).map_err(|e| format!("Line {}: {e}", number + 1)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Program)
+    }
+}
+
+// The disassembler, which gives back exactly what the puzzle input looks like
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|instruction| writeln!(f, "{instruction}"))
+    }
+}
+
+/// What the CPU looks like during a cycle: `cycle` counts from 1, and `x` is the register before the current
+/// instruction takes effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    pub cycle: usize,
+    pub x: isize,
+    pub pc: usize,
+    pub instruction: Instruction,
+}
+
+/// Called during every cycle. Breaking stops the CPU at the end of that cycle, and running it again resumes from
+/// the next one.
+pub trait Hook {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()>;
+}
+
+impl<F: FnMut(&Tick) -> ControlFlow<()>> Hook for F {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()> {
+        self(tick)
+    }
+}
+
+// Adds up cycle * x during the first cycle given, and every so many cycles after that
+pub struct SignalStrength {
+    first: usize,
+    every: usize,
+    pub total: isize,
+}
+
+impl SignalStrength {
+    pub fn new(first: usize, every: usize) -> Self {
+        Self { first, every, total: 0 }
+    }
+}
+
+impl Hook for SignalStrength {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()> {
+        if tick.cycle >= self.first && (tick.cycle - self.first).is_multiple_of(self.every) {
+            self.total += tick.cycle as isize * tick.x;
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+// Stops the CPU at the end of the given cycle
+pub struct Breakpoint {
+    pub cycle: usize,
+}
+
+impl Hook for Breakpoint {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()> {
+        if tick.cycle == self.cycle {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+// Records a line describing every cycle
+#[derive(Debug, Default)]
+pub struct TraceLog {
+    pub lines: Vec<String>,
+}
+
+impl Hook for TraceLog {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()> {
+        self.lines
+            .push(format!("cycle {}: x = {}, pc = {}, {}", tick.cycle, tick.x, tick.pc, tick.instruction));
+        ControlFlow::Continue(())
+    }
+}
+
+// Draws one pixel per cycle, left to right and top to bottom, lit if it's under the three pixel wide sprite centred
+// on x. The beam wraps back to the top left after the last pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crt {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Crt {
+    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err(format!("A {width}x{height} screen has no pixels to draw"));
+        }
+        Ok(Self { width, height, pixels: vec![false; width * height] })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether each pixel is lit, a row at a time
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+}
+
+impl Hook for Crt {
+    fn tick(&mut self, tick: &Tick) -> ControlFlow<()> {
+        let position = (tick.cycle - 1) % (self.width * self.height);
+        let column = (position % self.width) as isize;
+        self.pixels[position] = (column - tick.x).abs() <= 1;
+        ControlFlow::Continue(())
+    }
+}
+
+impl Display for Crt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.pixels.chunks(self.width).try_for_each(|row| {
+            let line = row
+                .iter()
+                .map(|lit| if *lit { '#' } else { '.' })
+                .collect::<String>();
+            writeln!(f, "{line}")
         })
-        .sum()
+    }
 }
 
-fn is_in_sprite(x: isize, sprite_pos: isize) -> bool {
-    x + 1 == sprite_pos || x == sprite_pos || sprite_pos + 1 == x
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stop {
+    Halted,
+    Breakpoint { cycle: usize },
 }
 
-fn end_of_line(cycles: isize) -> bool {
-    (cycles % 40) == 0
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CPU {
+    pub x: isize,
+    pub cycle: usize,
+    pub pc: usize,
+    // How many cycles of the current instruction have passed
+    progress: usize,
 }
 
-fn render(s: &mut String, x: isize, cycles: isize) {
-    let c = if is_in_sprite((cycles - 1) % 40, x) { '#' } else { '.' };
-    s.push(c);
-    if end_of_line(cycles) {
-        s.push('\n');
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-fn part2_evaluate(s: &str) -> String {
-    let mut crt = String::with_capacity(240);
-    let mut cpu = CPU::new();
-    for line in s.lines() {
-        match line.split_once(' ') {
-            Some(("addx", delta)) => {
-                render(&mut crt, cpu.x, cpu.cycles + 1);
-                render(&mut crt, cpu.x, cpu.cycles + 2);
-                cpu.addx(
-                    delta
-                        .parse::<isize>()
-                        .unwrap_or_else(|_| panic!("Bad operand in {line}")),
-                );
+impl CPU {
+    pub fn new() -> Self {
+        Self { x: 1, cycle: 0, pc: 0, progress: 0 }
+    }
+
+    pub fn run(&mut self, program: &Program, hooks: &mut [&mut dyn Hook]) -> Stop {
+        while let Some(&instruction) = program.0.get(self.pc) {
+            self.cycle += 1;
+            let tick = Tick { cycle: self.cycle, x: self.x, pc: self.pc, instruction };
+            // Every hook sees every cycle, even if an earlier one asks to stop
+            let mut stop = false;
+            for hook in hooks.iter_mut() {
+                stop |= hook.tick(&tick).is_break();
+            }
+            self.progress += 1;
+            if self.progress == instruction.cycles() {
+                if let Instruction::Addx(delta) = instruction {
+                    self.x += delta;
+                }
+                self.pc += 1;
+                self.progress = 0;
             }
-            None if line == "noop" => {
-                render(&mut crt, cpu.x, cpu.cycles + 1);
-                cpu.noop();
+            if stop {
+                return Stop::Breakpoint { cycle: self.cycle };
             }
-            _ => panic!("Bad line {line}"),
-        };
+        }
+        Stop::Halted
     }
-    crt
+}
+
+pub fn parse(s: &str) -> Program {
+    Program::from_str(s).unwrap_or_else(|e| panic!("{e}"))
+}
+
+fn part1_evaluate(s: &str) -> isize {
+    let mut probe = SignalStrength::new(20, 40);
+    CPU::new().run(&parse(s), &mut [&mut probe]);
+    probe.total
+}
+
+fn part2_evaluate(s: &str) -> String {
+    let mut crt = Crt::new(40, 6).unwrap();
+    CPU::new().run(&parse(s), &mut [&mut crt]);
+    crt.to_string()
 }
 
 #[cfg(test)]
@@ -88,15 +270,72 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 13_140);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), include_str!("./test_output.txt"));
 }
 
+#[cfg(test)]
+#[test]
+fn test_assembler() {
+    let program = parse(TEST_INPUT_STRING);
+    assert_eq!(program.to_string(), TEST_INPUT_STRING);
+    assert_eq!(parse(&program.to_string()), program);
+    assert_eq!(
+        parse("addx 3 ; x is now 4\n\n  noop\naddx -4"),
+        Program(vec![Instruction::Addx(3), Instruction::Noop, Instruction::Addx(-4)])
+    );
+    assert_eq!(Program::from_str("noop\naddx"), Err("Line 2: Bad instruction addx".to_owned()));
+    assert_eq!(Program::from_str("addx x"), Err("Line 1: Bad operand in addx x".to_owned()));
+}
+
+#[cfg(test)]
+#[test]
+fn test_hooks() {
+    let program = parse("noop\naddx 3\naddx -5");
+    let mut cpu = CPU::new();
+    let mut log = TraceLog::default();
+    let mut breakpoint = Breakpoint { cycle: 3 };
+    assert_eq!(cpu.run(&program, &mut [&mut log, &mut breakpoint]), Stop::Breakpoint { cycle: 3 });
+    assert_eq!(cpu.x, 4);
+    let mut xs = Vec::new();
+    let mut record = |tick: &Tick| {
+        xs.push(tick.x);
+        ControlFlow::Continue(())
+    };
+    assert_eq!(cpu.run(&program, &mut [&mut log, &mut record]), Stop::Halted);
+    assert_eq!(cpu.x, -1);
+    assert_eq!(xs, [4, 4]);
+    assert_eq!(
+        log.lines,
+        [
+            "cycle 1: x = 1, pc = 0, noop",
+            "cycle 2: x = 1, pc = 1, addx 3",
+            "cycle 3: x = 1, pc = 1, addx 3",
+            "cycle 4: x = 4, pc = 2, addx -5",
+            "cycle 5: x = 4, pc = 2, addx -5",
+        ]
+    );
+
+    let mut crt = Crt::new(4, 2).unwrap();
+    CPU::new().run(&parse("addx 2\nnoop\nnoop\nnoop"), &mut [&mut crt]);
+    assert_eq!(crt.to_string(), "####\n....\n");
+    // A smaller screen just wraps around sooner, and the last two cycles draw over the first two pixels
+    CPU::new().run(&parse("addx 5\nnoop\nnoop\nnoop\nnoop\nnoop\nnoop\naddx 0"), &mut [&mut crt]);
+    assert_eq!(crt.to_string(), "....\n....\n");
+    assert!(Crt::new(0, 6).is_err());
+    assert!(Crt::new(40, 0).is_err());
+}
+
+pub fn solve(input_string: &str) -> (isize, String) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(isize, bool, String, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     let part2_answer_good = &part2_answer == include_str!("./output.txt");
     Some((part1_answer, part1_answer == 15_680, part2_answer, part2_answer_good))
 }
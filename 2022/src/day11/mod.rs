@@ -1,68 +1,291 @@
+use std::fmt::{Debug, Display};
 use std::str::FromStr;
 
+use super::profile;
 use itertools::Itertools;
-
-#[derive(Debug, Clone, Copy)]
-struct Item {
-    worry_level: usize,
-    owner: usize,
-}
+use tracing::{debug, debug_span, trace};
 
 #[derive(Debug)]
-enum ParseErrors {
+pub enum ParseErrors {
     Operand,
     Inspection,
     Monkey,
 }
 
-#[derive(Debug)]
-enum Operand {
+/// How worried you get when a monkey inspects an item: an expression in `old` with `+`, `-`, `*`, `^` and brackets.
+/// Exponents have to be literal numbers.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Inspection {
     Old,
     Value(usize),
+    Add(Box<Inspection>, Box<Inspection>),
+    Subtract(Box<Inspection>, Box<Inspection>),
+    Multiply(Box<Inspection>, Box<Inspection>),
+    Power(Box<Inspection>, u32),
 }
 
-impl FromStr for Operand {
-    type Err = ParseErrors;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "old" {
-            Ok(Operand::Old)
+impl Inspection {
+    fn precedence(&self) -> u8 {
+        match self {
+            Inspection::Add(..) | Inspection::Subtract(..) => 1,
+            Inspection::Multiply(..) => 2,
+            Inspection::Power(..) => 3,
+            Inspection::Old | Inspection::Value(_) => 4,
+        }
+    }
+
+    // Everything is reduced by the modulus as it goes, if there is one, so that nothing gets any bigger than it
+    fn evaluate<W: Worry>(&self, old: &W, modulus: Option<usize>) -> W {
+        let reduce = |w: W| modulus.map_or_else(|| w.clone(), |m| w.reduce(m));
+        match self {
+            Inspection::Old => old.clone(),
+            Inspection::Value(v) => reduce(W::from_level(*v)),
+            Inspection::Add(l, r) => reduce(l.evaluate(old, modulus).add(&r.evaluate(old, modulus))),
+            Inspection::Subtract(l, r) => reduce(l.evaluate(old, modulus).sub(&r.evaluate(old, modulus))),
+            Inspection::Multiply(l, r) => reduce(l.evaluate(old, modulus).mul(&r.evaluate(old, modulus))),
+            Inspection::Power(base, exponent) => {
+                let mut square = base.evaluate(old, modulus);
+                let mut result = reduce(W::from_level(1));
+                let mut exponent = *exponent;
+                while exponent > 0 {
+                    if exponent & 1 == 1 {
+                        result = reduce(result.mul(&square));
+                    }
+                    exponent >>= 1;
+                    if exponent > 0 {
+                        square = reduce(square.mul(&square));
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Only bracket what needs it, which for the right hand side of a binary operator includes an operator with
+        // the same precedence, since they're all parsed left to right
+        let operand = |f: &mut std::fmt::Formatter<'_>, i: &Inspection, min_precedence: u8| {
+            if i.precedence() < min_precedence {
+                write!(f, "({i})")
+            } else {
+                write!(f, "{i}")
+            }
+        };
+        let binary = |f: &mut std::fmt::Formatter<'_>, l: &Inspection, op: &str, r: &Inspection| {
+            operand(f, l, self.precedence())?;
+            write!(f, " {op} ")?;
+            operand(f, r, self.precedence() + 1)
+        };
+        match self {
+            Inspection::Old => write!(f, "old"),
+            Inspection::Value(v) => write!(f, "{v}"),
+            Inspection::Add(l, r) => binary(f, l, "+", r),
+            Inspection::Subtract(l, r) => binary(f, l, "-", r),
+            Inspection::Multiply(l, r) => binary(f, l, "*", r),
+            Inspection::Power(base, exponent) => {
+                operand(f, base, 4)?;
+                write!(f, " ^ {exponent}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Token<'a> {
+    Number(&'a str),
+    Old,
+    Symbol(char),
+}
+
+fn tokenise(s: &str) -> Result<Vec<Token<'_>>, ParseErrors> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(&rest[..end]));
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("old") {
+            tokens.push(Token::Old);
+            rest = after;
+        } else if "+-*^()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            rest = &rest[1..];
         } else {
-            s.parse::<usize>()
-                .map_err(|_| Self::Err::Operand)
-                .map(Operand::Value)
+            return Err(ParseErrors::Operand);
         }
+        rest = rest.trim_start();
     }
+    Ok(tokens)
 }
 
-#[derive(Debug)]
-enum Inspection {
-    Add(Operand, Operand),
-    Multiply(Operand, Operand),
+// A recursive descent parser, with a function per level of precedence
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn sum(&mut self) -> Result<Inspection, ParseErrors> {
+        let mut left = self.product()?;
+        while let Some(Token::Symbol(op @ ('+' | '-'))) = self.peek() {
+            self.next();
+            let right = Box::new(self.product()?);
+            left = if op == '+' {
+                Inspection::Add(Box::new(left), right)
+            } else {
+                Inspection::Subtract(Box::new(left), right)
+            };
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Inspection, ParseErrors> {
+        let mut left = self.power()?;
+        while let Some(Token::Symbol('*')) = self.peek() {
+            self.next();
+            left = Inspection::Multiply(Box::new(left), Box::new(self.power()?));
+        }
+        Ok(left)
+    }
+
+    fn power(&mut self) -> Result<Inspection, ParseErrors> {
+        let base = self.atom()?;
+        if let Some(Token::Symbol('^')) = self.peek() {
+            self.next();
+            match self.next() {
+                Some(Token::Number(n)) => {
+                    let exponent = n.parse::<u32>().map_err(|_| ParseErrors::Operand)?;
+                    return Ok(Inspection::Power(Box::new(base), exponent));
+                }
+                _ => return Err(ParseErrors::Inspection),
+            }
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Inspection, ParseErrors> {
+        match self.next() {
+            Some(Token::Old) => Ok(Inspection::Old),
+            Some(Token::Number(n)) => n
+                .parse::<usize>()
+                .map(Inspection::Value)
+                .map_err(|_| ParseErrors::Operand),
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                match self.next() {
+                    Some(Token::Symbol(')')) => Ok(inner),
+                    _ => Err(ParseErrors::Inspection),
+                }
+            }
+            _ => Err(ParseErrors::Inspection),
+        }
+    }
 }
 
 impl FromStr for Inspection {
     type Err = ParseErrors;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut tokens = s.split_whitespace();
-        let left = tokens
-            .next()
-            .ok_or(Self::Err::Inspection)
-            .and_then(Operand::from_str);
-        let op = tokens.next().ok_or(Self::Err::Inspection);
-        let right = tokens
-            .next()
-            .ok_or(Self::Err::Inspection)
-            .and_then(Operand::from_str);
-        if let (Ok(left), Ok(op), Ok(right)) = (left, op, right) {
-            match op {
-                "+" => Ok(Self::Add(left, right)),
-                "*" => Ok(Self::Multiply(left, right)),
-                _ => Err(Self::Err::Inspection),
-            }
+        let mut parser = Parser { tokens: tokenise(s)?, position: 0 };
+        let inspection = parser.sum()?;
+        if parser.peek().is_some() {
+            return Err(Self::Err::Inspection);
+        }
+        Ok(inspection)
+    }
+}
+
+/// The arithmetic worry levels need. Levels are signed, since subtraction can take them below zero.
+trait Worry: Clone + Debug {
+    fn from_level(level: usize) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    // Always gives a result between 0 and the modulus
+    fn reduce(&self, modulus: usize) -> Self;
+    // Rounds down
+    fn divide(&self, divisor: usize) -> Self;
+    fn is_divisible_by(&self, divisor: usize) -> bool;
+}
+
+// Without a modulus, anything much beyond the part 1 rules overflows, which needs the `exact` feature
+impl Worry for i128 {
+    fn from_level(level: usize) -> Self {
+        level as i128
+    }
+    fn add(&self, other: &Self) -> Self {
+        self.checked_add(*other).expect("Worry level overflowed")
+    }
+    fn sub(&self, other: &Self) -> Self {
+        self.checked_sub(*other).expect("Worry level overflowed")
+    }
+    fn mul(&self, other: &Self) -> Self {
+        self.checked_mul(*other).expect("Worry level overflowed")
+    }
+    fn reduce(&self, modulus: usize) -> Self {
+        self.rem_euclid(modulus as i128)
+    }
+    fn divide(&self, divisor: usize) -> Self {
+        self.div_euclid(divisor as i128)
+    }
+    fn is_divisible_by(&self, divisor: usize) -> bool {
+        self % divisor as i128 == 0
+    }
+}
+
+#[cfg(feature = "exact")]
+impl Worry for num_bigint::BigInt {
+    fn from_level(level: usize) -> Self {
+        level.into()
+    }
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+    fn sub(&self, other: &Self) -> Self {
+        self - other
+    }
+    fn mul(&self, other: &Self) -> Self {
+        self * other
+    }
+    fn reduce(&self, modulus: usize) -> Self {
+        let modulus = Self::from(modulus);
+        ((self % &modulus) + &modulus) % modulus
+    }
+    fn divide(&self, divisor: usize) -> Self {
+        // BigInt division truncates towards zero
+        let divisor = Self::from(divisor);
+        let quotient = self / &divisor;
+        if self.sign() == num_bigint::Sign::Minus && &quotient * &divisor != *self {
+            quotient - 1
         } else {
-            Err(Self::Err::Inspection)
+            quotient
         }
     }
+    fn is_divisible_by(&self, divisor: usize) -> bool {
+        (self % Self::from(divisor)) == Self::from(0)
+    }
+}
+
+/// What keeps worry levels manageable after each inspection. Dividing by 1 is no relief at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relief {
+    Divide(usize),
+    // Doesn't change where any item is thrown, as long as every divisor divides the modulus
+    Modulo(usize),
 }
 
 #[derive(Debug)]
@@ -73,8 +296,8 @@ struct ThrowTo {
 }
 
 impl ThrowTo {
-    fn throw(&self, item: &Item) -> usize {
-        if item.worry_level % self.divisor == 0 {
+    fn throw<W: Worry>(&self, worry_level: &W) -> usize {
+        if worry_level.is_divisible_by(self.divisor) {
             self.if_true
         } else {
             self.if_false
@@ -82,28 +305,19 @@ impl ThrowTo {
     }
 }
 
-struct Monkey {
+#[derive(Debug)]
+pub struct Monkey {
     id: usize,
-    initial_items: Vec<Item>,
-    inspection: Box<dyn Fn(usize) -> usize>,
+    initial_items: Vec<usize>,
+    inspection: Inspection,
     throw_to: ThrowTo,
-    inspection_count: usize,
-    // worry_div_factor: usize,
-    // worry_mod_factor: usize,
-    deworrier: Box<dyn Fn(usize) -> usize>,
 }
 
 impl Monkey {
-    fn process_item(&mut self, item: &mut Item) {
-        self.inspection_count += 1;
-        item.worry_level = (self.deworrier)((self.inspection)(item.worry_level));
-        item.owner = self.throw_to.throw(item);
-    }
-    fn process_items(&mut self, items: &mut Vec<Item>) {
-        for item in items {
-            if item.owner == self.id {
-                self.process_item(item);
-            }
+    fn inspect<W: Worry>(&self, worry_level: &W, relief: Relief) -> W {
+        match relief {
+            Relief::Divide(divisor) => self.inspection.evaluate(worry_level, None).divide(divisor),
+            Relief::Modulo(modulus) => self.inspection.evaluate(worry_level, Some(modulus)),
         }
     }
 }
@@ -125,12 +339,7 @@ impl FromStr for Monkey {
             .and_then(|items| {
                 items
                     .split(',')
-                    .map(|num| {
-                        num.trim()
-                            .parse::<usize>()
-                            .map(|worry_level| Item { worry_level, owner: id })
-                            .map_err(|_| Self::Err::Monkey)
-                    })
+                    .map(|num| num.trim().parse::<usize>().map_err(|_| Self::Err::Monkey))
                     .collect::<Result<Vec<_>, _>>()
             })?;
         let inspection = line
@@ -139,26 +348,12 @@ impl FromStr for Monkey {
             .ok_or(Self::Err::Monkey)
             .and_then(Inspection::from_str)?;
 
-        let inspection: Box<dyn Fn(usize) -> usize> = match &inspection {
-            Inspection::Add(l, r) => match (l, r) {
-                (&Operand::Old, &Operand::Old) => Box::new(|old| 2 * old),
-                (&Operand::Old, &Operand::Value(v)) => Box::new(move |old| old + v),
-                (&Operand::Value(v), &Operand::Old) => Box::new(move |old| v + old),
-                (&Operand::Value(v1), &Operand::Value(v2)) => Box::new(move |_old| v1 + v2),
-            },
-            Inspection::Multiply(l, r) => match (l, r) {
-                (&Operand::Old, &Operand::Old) => Box::new(|old| old * old),
-                (&Operand::Old, &Operand::Value(v)) => Box::new(move |old| old * v),
-                (&Operand::Value(v), &Operand::Old) => Box::new(move |old| v * old),
-                (&Operand::Value(v1), &Operand::Value(v2)) => Box::new(move |_old| v1 * v2),
-            },
-        };
-
         let divisor = line
             .next()
             .and_then(|s| s.trim().strip_prefix("Test: divisible by "))
             .ok_or(Self::Err::Monkey)
-            .and_then(|s| s.parse::<usize>().map_err(|_| Self::Err::Monkey))?;
+            .and_then(|s| s.parse::<usize>().map_err(|_| Self::Err::Monkey))
+            .and_then(|d| if d == 0 { Err(Self::Err::Monkey) } else { Ok(d) })?;
 
         let if_true = line
             .next()
@@ -172,69 +367,139 @@ impl FromStr for Monkey {
             .ok_or(Self::Err::Monkey)
             .and_then(|s| s.parse::<usize>().map_err(|_| Self::Err::Monkey))?;
 
-        Ok(Self {
-            id,
-            initial_items,
-            inspection,
-            throw_to: ThrowTo { divisor, if_true, if_false },
-            inspection_count: 0,
-            deworrier: Box::new(|old| old / 3),
-        })
+        Ok(Self { id, initial_items, inspection, throw_to: ThrowTo { divisor, if_true, if_false } })
     }
 }
 
-fn parse(s: &str) -> (Vec<Monkey>, Vec<Item>) {
+pub fn parse(s: &str) -> Vec<Monkey> {
     let monkeys = s
         .replace("\r\n", "\n")
         .split("\n\n")
         .map(|lines| Monkey::from_str(lines).unwrap_or_else(|_| panic!("Bad monkey in {lines}")))
         .collect::<Vec<_>>();
-    let items = monkeys
-        .iter()
-        .flat_map(|m| m.initial_items.clone())
-        .collect::<Vec<_>>();
-    (monkeys, items)
+    for (index, monkey) in monkeys.iter().enumerate() {
+        assert_eq!(monkey.id, index, "Monkeys are out of order");
+        assert!(
+            monkey.throw_to.if_true < monkeys.len() && monkey.throw_to.if_false < monkeys.len(),
+            "Monkey {index} throws to a monkey that doesn't exist"
+        );
+    }
+    monkeys
 }
 
-fn part1_evaluate(s: &str) -> usize {
-    let (mut monkeys, mut items) = parse(s);
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
 
-    for _ in 0..20 {
-        for m in &mut monkeys {
-            m.process_items(&mut items);
-        }
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+// The smallest modulus that every monkey's test still works with
+pub fn modulus(monkeys: &[Monkey]) -> usize {
+    monkeys.iter().map(|m| m.throw_to.divisor).fold(1, lcm)
+}
+
+/// What happened in a round: how many items each monkey held at the end of it, and how many items each monkey
+/// threw to each other monkey, indexed `[from][to]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Round {
+    pub holding: Vec<usize>,
+    pub throws: Vec<Vec<usize>>,
+}
+
+// The rounds are only recorded when asked for, as there'd be thousands of them otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub inspections: Vec<usize>,
+    pub rounds: Vec<Round>,
+}
+
+impl Stats {
+    pub fn monkey_business(&self) -> usize {
+        self.inspections
+            .iter()
+            .sorted()
+            .rev()
+            .take(2)
+            .product::<usize>()
     }
-    monkeys
-        .into_iter()
-        .map(|m| m.inspection_count)
-        .sorted()
-        .rev()
-        .take(2)
-        .product::<usize>()
 }
 
-fn part2_evaluate(s: &str) -> usize {
-    let (mut monkeys, mut items) = parse(s);
-    let worry_mod_factor = monkeys
+fn simulate<W: Worry>(monkeys: &[Monkey], round_count: usize, relief: Relief) -> Stats {
+    simulate_recording::<W>(monkeys, round_count, relief, false)
+}
+
+// Like `simulate`, but keeps a `Round` for every round as well
+fn simulate_rounds<W: Worry>(monkeys: &[Monkey], round_count: usize, relief: Relief) -> Stats {
+    simulate_recording::<W>(monkeys, round_count, relief, true)
+}
+
+fn simulate_recording<W: Worry>(monkeys: &[Monkey], round_count: usize, relief: Relief, record: bool) -> Stats {
+    let mut items = monkeys
         .iter()
-        .map(|m| m.throw_to.divisor)
-        .product::<usize>();
-    for m in monkeys.iter_mut() {
-        m.deworrier = Box::new(move |old| old % worry_mod_factor);
-    }
-    for _ in 0..10_000 {
-        for m in &mut monkeys {
-            m.process_items(&mut items);
+        .map(|m| {
+            m.initial_items
+                .iter()
+                .map(|level| W::from_level(*level))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut stats = Stats { inspections: vec![0; monkeys.len()], rounds: Vec::new() };
+    for round in 1..=round_count {
+        let _span = debug_span!("round", round).entered();
+        let mut throws = record.then(|| vec![vec![0; monkeys.len()]; monkeys.len()]);
+        for m in monkeys {
+            for worry_level in std::mem::take(&mut items[m.id]) {
+                let worry_level = m.inspect(&worry_level, relief);
+                let thrown_to = m.throw_to.throw(&worry_level);
+                trace!(monkey = m.id, ?worry_level, thrown_to, "inspected item");
+                stats.inspections[m.id] += 1;
+                if let Some(throws) = &mut throws {
+                    throws[m.id][thrown_to] += 1;
+                }
+                items[thrown_to].push(worry_level);
+            }
+        }
+        debug!(counts = ?stats.inspections, "end of round");
+        if let Some(throws) = throws {
+            stats
+                .rounds
+                .push(Round { holding: items.iter().map(Vec::len).collect(), throws });
         }
     }
+    stats
+}
 
-    monkeys
-        .into_iter()
-        .map(|m| m.inspection_count)
-        .sorted()
-        .rev()
-        .take(2)
-        .product::<usize>()
+/// Plays the rounds, keeping how many items each monkey held and threw to each other monkey after every round
+pub fn round_stats(monkeys: &[Monkey], round_count: usize, relief: Relief) -> Stats {
+    simulate_rounds::<i128>(monkeys, round_count, relief)
+}
+
+/// Like `round_stats`, but with worry levels that never overflow, so the rounds can be played without relief to check
+/// that a modulus gives the same answers
+#[cfg(feature = "exact")]
+pub fn exact_round_stats(monkeys: &[Monkey], round_count: usize, relief: Relief) -> Stats {
+    simulate_rounds::<num_bigint::BigInt>(monkeys, round_count, relief)
+}
+
+fn part1_evaluate(s: &str) -> usize {
+    let monkeys = parse(s);
+    simulate::<i128>(&monkeys, 20, Relief::Divide(3)).monkey_business()
+}
+
+fn part2_evaluate(s: &str) -> usize {
+    let monkeys = parse(s);
+    simulate::<i128>(&monkeys, 10_000, Relief::Modulo(modulus(&monkeys))).monkey_business()
+}
+
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = Monkey::from_str(s);
 }
 
 #[cfg(test)]
@@ -272,14 +537,109 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 10_605);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 2_713_310_158);
 }
 
+#[cfg(test)]
+#[test]
+fn test_inspections() {
+    let parse_inspection = |s: &str| Inspection::from_str(s).unwrap();
+    let i = parse_inspection("(old - 3) ^ 2 * 2 + old");
+    assert_eq!(i.evaluate(&5i128, None), 13);
+    assert_eq!(i.evaluate(&5i128, Some(7)), 6);
+    assert_eq!(i.to_string(), "(old - 3) ^ 2 * 2 + old");
+    assert_eq!(parse_inspection("old - (old - 1)").evaluate(&10i128, None), 1);
+    assert_eq!(parse_inspection("old - old - 1").evaluate(&10i128, None), -1);
+    assert_eq!(parse_inspection("((old))*(2+3)").to_string(), "old * (2 + 3)");
+    assert_eq!(parse_inspection("old ^ 100").evaluate(&3i128, Some(1_000_000_007)), 886_041_711);
+    for bad in ["", "old +", "(old", "old)", "old ^ old", "old $ 2", "2 3"] {
+        assert!(Inspection::from_str(bad).is_err(), "{bad}");
+    }
+
+    let monkeys = parse(TEST_INPUT_STRING);
+    assert_eq!(modulus(&monkeys), 96_577);
+    let stats = round_stats(&monkeys, 1, Relief::Divide(3));
+    assert_eq!(stats.inspections, [2, 4, 3, 5]);
+    assert_eq!(stats.rounds[0].holding, [4, 6, 0, 0]);
+    assert_eq!(stats.rounds[0].throws, [[0, 0, 0, 2], [4, 0, 0, 0], [0, 1, 0, 2], [0, 5, 0, 0]]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_modular_reduction() {
+    // An lcm rather than a product still gives exactly the same rounds as not reducing at all, for as long as the
+    // worry levels fit
+    let s = TEST_INPUT_STRING
+        .replace("divisible by 19", "divisible by 26")
+        .replace("new = old + 6", "new = (old - 2) * 3 - old");
+    let monkeys = parse(&s);
+    assert_eq!(modulus(&monkeys), 23 * 26 * 17);
+    let exact = simulate_rounds::<i128>(&monkeys, 4, Relief::Divide(1));
+    assert_eq!(simulate_rounds::<i128>(&monkeys, 4, Relief::Modulo(modulus(&monkeys))), exact);
+    assert_eq!(exact.rounds.len(), 4);
+    // Only the inspections are kept otherwise
+    let unrecorded = simulate::<i128>(&monkeys, 4, Relief::Divide(1));
+    assert_eq!((unrecorded.inspections, unrecorded.rounds), (exact.inspections, vec![]));
+}
+
+#[cfg(all(test, feature = "exact"))]
+#[test]
+fn test_exact() {
+    let monkeys = parse(TEST_INPUT_STRING);
+    let modular = round_stats(&monkeys, 12, Relief::Modulo(modulus(&monkeys)));
+    assert_eq!(exact_round_stats(&monkeys, 12, Relief::Divide(1)), modular);
+    assert_eq!(exact_round_stats(&monkeys, 20, Relief::Divide(3)), round_stats(&monkeys, 20, Relief::Divide(3)));
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 78_678, part2_answer, part2_answer == 15_333_249_714))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn inspection() -> impl Strategy<Value = Inspection> {
+        let leaf = prop_oneof![Just(Inspection::Old), any::<usize>().prop_map(Inspection::Value)];
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Inspection::Add(Box::new(l), Box::new(r))),
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Inspection::Subtract(Box::new(l), Box::new(r))),
+                (inner.clone(), inner.clone()).prop_map(|(l, r)| Inspection::Multiply(Box::new(l), Box::new(r))),
+                (inner, any::<u32>()).prop_map(|(b, e)| Inspection::Power(Box::new(b), e)),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn inspection_round_trips(i in inspection()) {
+            prop_assert_eq!(Inspection::from_str(&i.to_string()).ok(), Some(i));
+        }
+
+        #[test]
+        fn modular_evaluation_matches_exact(i in inspection(), old in -1000i128..1000, modulus in 1usize..1000) {
+            // Only where the exact value fits, which is most of the time for small expressions
+            let exact = std::panic::catch_unwind(|| i.evaluate(&old, None));
+            if let Ok(exact) = exact {
+                prop_assert_eq!(i.evaluate(&old.reduce(modulus), Some(modulus)), exact.reduce(modulus));
+            }
+        }
+
+        #[test]
+        fn monkey_parse_does_not_panic(s in "\\PC*") {
+            let _ = Monkey::from_str(&s);
+        }
+    }
+}
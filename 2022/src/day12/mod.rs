@@ -1,108 +1,178 @@
 use super::grid::{Grid, GridCoord};
+use super::profile;
+use std::collections::VecDeque;
 
-#[derive(Default, Debug, Copy, Clone)]
-struct GridPoint {
-    height: u8,
-    step_count: Option<u16>,
+pub struct Heightmap {
+    heights: Grid<u8>,
+    start: GridCoord,
+    end: GridCoord,
 }
 
-impl GridPoint {
-    fn new_with_steps(h: u8, steps: u16) -> Self {
-        GridPoint { height: h, step_count: Some(steps) }
+impl Heightmap {
+    /// The height of the square at `(x, y)`, from 0 for `a` up to 25 for `z`
+    pub fn height(&self, (x, y): (usize, usize)) -> Option<u8> {
+        self.heights.cell(GridCoord::from((x, y))).copied()
     }
-    fn new(h: u8) -> Self {
-        GridPoint { height: h, step_count: None }
+
+    pub fn start(&self) -> (usize, usize) {
+        (self.start.x, self.start.y)
+    }
+
+    pub fn end(&self) -> (usize, usize) {
+        (self.end.x, self.end.y)
     }
 }
 
-fn parse_grid(input: &str) -> (Grid<GridPoint>, GridCoord, GridCoord, Vec<GridCoord>) {
+pub fn parse_grid(input: &str) -> Heightmap {
     let width = input.lines().next().unwrap().len();
     let height = input.lines().count();
 
-    let mut grid = Grid::new(width, height);
+    let mut heights = Grid::new(width, height);
     let mut start = Option::None;
     let mut end = Option::None;
-    let mut lowest_points = Vec::new();
     for (y, line) in input.lines().enumerate() {
         for (x, col) in line.bytes().enumerate() {
             let here = GridCoord::from((x, y));
-            match col {
-                b'a' => {
-                    *grid.cell_mut(here).unwrap() = GridPoint::new(0);
-                    lowest_points.push(here);
-                }
-                b'b'..=b'z' => *grid.cell_mut(here).unwrap() = GridPoint::new(col - b'a'),
+            *heights.cell_mut(here).unwrap() = match col {
+                b'a'..=b'z' => col - b'a',
                 b'S' => {
-                    lowest_points.push(here);
                     start = Some(here);
-                    *grid.cell_mut(here).unwrap() = GridPoint::new_with_steps(0, 0);
+                    0
                 }
                 b'E' => {
                     end = Some(here);
-                    *grid.cell_mut(here).unwrap() = GridPoint::new(b'z' - b'a');
+                    b'z' - b'a'
                 }
                 c => panic!("Bad character {c} found in grid at {here:?}"),
-            }
+            };
         }
     }
 
     match start.zip(end) {
-        Some((start, end)) => (grid, start, end, lowest_points),
+        Some((start, end)) => Heightmap { heights, start, end },
         _ => panic!("No start or end point(s) found in grid"),
     }
 }
 
-fn can_move(points: &Grid<GridPoint>, from_height: u8, to_coord: GridCoord) -> Option<GridCoord> {
-    let to_point = points.cell(to_coord).expect("check.1");
-    (from_height + 2 > to_point.height && to_point.step_count.is_none()).then_some(to_coord)
+/// The squares visited, in order from where the climb starts to where it ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    squares: Vec<GridCoord>,
 }
 
-fn move_from(points: &mut Grid<GridPoint>, from_points: &[GridCoord]) -> Vec<GridCoord> {
-    from_points
-        .iter()
-        .flat_map(|p| {
-            let next_point = *points.cell(*p).expect("move_from.1");
-            let next_step = next_point.step_count.expect("move_from.2") + 1;
+impl Route {
+    pub fn steps(&self) -> usize {
+        self.squares.len() - 1
+    }
+
+    /// The squares along the route as `(x, y)`, starting where the climb starts
+    pub fn squares(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.squares.iter().map(|p| (p.x, p.y))
+    }
 
-            let next_points = [
-                (p.x > 0).then(|| GridCoord::from((p.x - 1, p.y))),
-                (p.y > 0).then(|| GridCoord::from((p.x, p.y - 1))),
-                (p.x < points.width() - 1).then(|| GridCoord::from((p.x + 1, p.y))),
-                (p.y < points.height() - 1).then(|| GridCoord::from((p.x, p.y + 1))),
-            ]
-            .into_iter()
-            .filter_map(|p| p.and_then(|p| can_move(points, next_point.height, p)))
-            .collect::<Vec<_>>();
-            next_points
-                .iter()
-                .for_each(|p| points.cell_mut(*p).expect("move_from.3").step_count = Some(next_step));
-            next_points
-        })
-        .collect::<Vec<_>>()
+    // Like the puzzle text, with an arrow on each square showing which way the route leaves it, `E` where it ends and
+    // `.` everywhere else
+    pub fn render(&self, map: &Heightmap) -> String {
+        let mut lines = vec![vec!['.'; map.heights.width()]; map.heights.height()];
+        let end = self.squares.last().unwrap();
+        lines[end.y][end.x] = 'E';
+        for (from, to) in self.squares.iter().zip(self.squares.iter().skip(1)) {
+            lines[from.y][from.x] = match (to.x as isize - from.x as isize, to.y as isize - from.y as isize) {
+                (1, 0) => '>',
+                (-1, 0) => '<',
+                (0, 1) => 'v',
+                _ => '^',
+            };
+        }
+        lines
+            .iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
-fn part1_evaluate(s: &str) -> usize {
-    let (mut points, start, end, _) = parse_grid(s);
+fn neighbours(grid: &Grid<u8>, p: GridCoord) -> impl Iterator<Item = GridCoord> {
+    [
+        (p.x > 0).then(|| GridCoord::from((p.x - 1, p.y))),
+        (p.y > 0).then(|| GridCoord::from((p.x, p.y - 1))),
+        (p.x < grid.width() - 1).then(|| GridCoord::from((p.x + 1, p.y))),
+        (p.y < grid.height() - 1).then(|| GridCoord::from((p.x, p.y + 1))),
+    ]
+    .into_iter()
+    .flatten()
+}
 
-    let mut moved_to = move_from(&mut points, &[start]);
-    while !moved_to.is_empty() {
-        moved_to = move_from(&mut points, &moved_to);
+// Breadth first from all the sources at once, remembering where each square was first reached from so that the
+// route can be traced back to whichever source turned out to be closest. `can_move` is given the heights of the
+// square being moved from and the square being moved to.
+fn search(
+    map: &Heightmap,
+    sources: &[GridCoord],
+    is_goal: impl Fn(GridCoord) -> bool,
+    can_move: impl Fn(u8, u8) -> bool,
+) -> Option<Route> {
+    let mut came_from = Grid::<Option<GridCoord>>::new(map.heights.width(), map.heights.height());
+    let mut visited = Grid::<bool>::new(map.heights.width(), map.heights.height());
+    let mut queue = VecDeque::new();
+    for source in sources {
+        *visited.cell_mut(*source).unwrap() = true;
+        queue.push_back(*source);
     }
+    while let Some(p) = queue.pop_front() {
+        if is_goal(p) {
+            let mut squares = vec![p];
+            while let Some(previous) = *came_from.cell(*squares.last().unwrap()).unwrap() {
+                squares.push(previous);
+            }
+            squares.reverse();
+            return Some(Route { squares });
+        }
+        let from_height = *map.heights.cell(p).unwrap();
+        for next in neighbours(&map.heights, p) {
+            if !visited.cell(next).unwrap() && can_move(from_height, *map.heights.cell(next).unwrap()) {
+                *visited.cell_mut(next).unwrap() = true;
+                *came_from.cell_mut(next).unwrap() = Some(p);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
 
-    points.cell(end).unwrap().step_count.unwrap() as usize
+// The puzzle's rule: any step down, but at most one step up
+pub fn can_climb(from: u8, to: u8) -> bool {
+    to <= from + 1
 }
 
-fn part2_evaluate(s: &str) -> usize {
-    let (mut points, _, end, mut lowest_points) = parse_grid(s);
+/// The shortest route from `S` to `E`, taking only the steps `can_climb` allows between the heights of two squares
+pub fn climb(map: &Heightmap, can_climb: impl Fn(u8, u8) -> bool) -> Option<Route> {
+    search(map, &[map.start], |p| p == map.end, can_climb)
+}
 
-    lowest_points
-        .iter()
-        .for_each(|p| points.cell_mut(*p).expect("part2_evaluate.1").step_count = Some(0));
-    while !lowest_points.is_empty() {
-        lowest_points = move_from(&mut points, &lowest_points);
-    }
+// Searches backwards from `E` to the nearest square that could be a start, checking each step the way a climber
+// would take it, and gives back the route the right way round
+pub fn climb_from_best_start(
+    map: &Heightmap,
+    is_start: impl Fn((usize, usize)) -> bool,
+    can_climb: impl Fn(u8, u8) -> bool,
+) -> Option<Route> {
+    search(map, &[map.end], |p| is_start((p.x, p.y)), |from, to| can_climb(to, from)).map(|mut route| {
+        route.squares.reverse();
+        route
+    })
+}
+
+fn part1_evaluate(s: &str) -> usize {
+    let map = parse_grid(s);
+    climb(&map, can_climb).expect("No route to the top").steps()
+}
 
-    points.cell(end).unwrap().step_count.unwrap() as usize
+fn part2_evaluate(s: &str) -> usize {
+    let map = parse_grid(s);
+    climb_from_best_start(&map, |p| map.height(p) == Some(0), can_climb)
+        .expect("No route to the top")
+        .steps()
 }
 
 #[cfg(test)]
@@ -119,14 +189,73 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 31);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 29);
 }
 
+#[cfg(test)]
+#[test]
+fn test_routes() {
+    let map = parse_grid(TEST_INPUT_STRING);
+    for route in
+        [climb(&map, can_climb).unwrap(), climb_from_best_start(&map, |p| map.height(p) == Some(0), can_climb).unwrap()]
+    {
+        assert_eq!(route.squares.last(), Some(&map.end));
+        for (from, to) in route.squares.iter().zip(route.squares.iter().skip(1)) {
+            assert_eq!(from.x.abs_diff(to.x) + from.y.abs_diff(to.y), 1);
+            assert!(can_climb(*map.heights.cell(*from).unwrap(), *map.heights.cell(*to).unwrap()));
+        }
+    }
+    assert_eq!(climb(&map, can_climb).unwrap().squares().next(), Some(map.start()));
+
+    // The route in the puzzle text is one of several just as short, so follow its arrows to check that it renders
+    // the same and is as short as the route found
+    let diagram = "v..v<<<<\n>v.vv<<^\n.>vv>E^^\n..v>>>^^\n..>>>>>^";
+    let rows = diagram.lines().map(str::as_bytes).collect::<Vec<_>>();
+    let mut squares = vec![map.start];
+    while let Some(arrow) = rows[squares.last().unwrap().y].get(squares.last().unwrap().x) {
+        let here = *squares.last().unwrap();
+        let next = match arrow {
+            b'>' => (here.x + 1, here.y),
+            b'<' => (here.x - 1, here.y),
+            b'v' => (here.x, here.y + 1),
+            b'^' => (here.x, here.y - 1),
+            _ => break,
+        };
+        squares.push(GridCoord::from(next));
+    }
+    let puzzle_route = Route { squares };
+    assert_eq!(puzzle_route.render(&map), diagram);
+    assert_eq!(puzzle_route.steps(), climb(&map, can_climb).unwrap().steps());
+    assert_eq!(puzzle_route.squares.last(), Some(&map.end));
+    assert!(puzzle_route
+        .squares
+        .windows(2)
+        .all(|w| can_climb(*map.heights.cell(w[0]).unwrap(), *map.heights.cell(w[1]).unwrap())));
+    assert_eq!(climb(&map, can_climb).unwrap().render(&map), ">>vv<<<<\n..vvv<<^\n..vv>E^^\n..v>>>^^\n..>>>>>^");
+
+    // The shortest route never drops more than one step at a time anyway, and searching back from the end to a
+    // single start finds a route just as short as searching forwards
+    let gentle = |from: u8, to: u8| to <= from + 1 && from <= to + 1;
+    assert_eq!(climb(&map, gentle).unwrap().steps(), 31);
+    assert_eq!(
+        climb_from_best_start(&map, |p| p == map.start(), gentle)
+            .unwrap()
+            .steps(),
+        31
+    );
+    assert_eq!(climb(&map, |from, to| to == from + 1), None);
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 456, part2_answer, part2_answer == 454))
 }
@@ -1,35 +1,96 @@
-use itertools::Itertools;
+use super::profile;
 use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+/// A packet, or any part of one. Values are ordered by the puzzle's rules, under which an integer compares the same
+/// as a list holding just that integer, so equality is by the same rules: `2`, `[2]` and `[[2]]` are all equal,
+/// even though they're written differently.
+#[derive(Debug, Clone)]
 enum Value {
     List(Vec<Value>),
     Integer(usize),
 }
 
+/// Builds a `Value` from a literal written the way it would be printed, e.g. `packet!([[1], 2, []])`
+macro_rules! packet {
+    ([$($element:tt),* $(,)?]) => {
+        Value::List(vec![$(packet!($element)),*])
+    };
+    ($integer:expr) => {
+        Value::Integer($integer)
+    };
+}
+
 mod parser {
     use super::Value;
     use nom::branch::alt;
     use nom::bytes::complete::tag;
-    use nom::character::complete::u32;
-    use nom::combinator::map;
-    use nom::multi::separated_list0;
-    use nom::sequence::delimited;
+    use nom::character::complete::u64;
+    use nom::combinator::{cut, map, opt};
+    use nom::multi::many0;
+    use nom::sequence::{delimited, pair, preceded};
     use nom::IResult;
     pub(super) fn value(s: &str) -> IResult<&str, Value> {
-        alt((map(u32, |v| Value::Integer(v as usize)), list))(s)
+        alt((map(u64, |v| Value::Integer(v as usize)), list))(s)
     }
 
+    // A `,` has to be followed by another value, so a bad one is reported where it is rather than back at the `,`
     pub(super) fn list(s: &str) -> IResult<&str, Value> {
-        delimited(tag("["), map(separated_list0(tag(","), value), Value::List), tag("]"))(s)
+        let values = map(opt(pair(value, many0(preceded(tag(","), cut(value))))), |values| {
+            values.map_or_else(Vec::new, |(first, rest)| std::iter::once(first).chain(rest).collect())
+        });
+        delimited(tag("["), map(values, Value::List), tag("]"))(s)
+    }
+}
+
+/// Where parsing stopped, counting columns from 1, and what was found there.
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    column: usize,
+    found: Option<char>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.found {
+            Some(c) => write!(f, "Unexpected '{c}' at column {}", self.column),
+            None => write!(f, "Unexpected end of input at column {}", self.column),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum IsLower {
-    Left,
-    Equal,
-    Right,
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error_at = |rest: &str| ParseError { column: s.len() - rest.len() + 1, found: rest.chars().next() };
+        match parser::value(s) {
+            Ok(("", value)) => Ok(value),
+            Ok((rest, _)) => Err(error_at(rest)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(error_at(e.input)),
+            Err(nom::Err::Incomplete(_)) => Err(error_at("")),
+        }
+    }
+}
+
+// Exactly the way the puzzle writes packets, so anything parsed from there prints back the same
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(v) => write!(f, "{v}"),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 impl From<usize> for Value {
@@ -44,45 +105,46 @@ impl From<Vec<usize>> for Value {
     }
 }
 
-impl Value {
-    fn compare(&self, rhs: &Self) -> IsLower {
+impl Ord for Value {
+    fn cmp(&self, rhs: &Self) -> Ordering {
         match (self, rhs) {
-            (Self::Integer(l), Self::Integer(r)) => match l.cmp(r) {
-                std::cmp::Ordering::Less => IsLower::Left,
-                std::cmp::Ordering::Equal => IsLower::Equal,
-                std::cmp::Ordering::Greater => IsLower::Right,
-            },
-            (Self::Integer(l), r @ Self::List(_)) => {
-                let l = Self::from(vec![*l]);
-                l.compare(r)
-            }
-            (l @ Self::List(_), Self::Integer(r)) => {
-                let r = Self::from(vec![*r]);
-                l.compare(&r)
-            }
-            (Self::List(l), Self::List(r)) => l
-                .iter()
-                .zip(r.iter())
-                .find_map(|(l, r)| {
-                    let cmp_res = l.compare(r);
-                    (cmp_res != IsLower::Equal).then_some(cmp_res)
-                })
-                .unwrap_or(match l.len().cmp(&r.len()) {
-                    std::cmp::Ordering::Less => IsLower::Left,
-                    std::cmp::Ordering::Equal => IsLower::Equal,
-                    std::cmp::Ordering::Greater => IsLower::Right,
-                }),
+            (Self::Integer(l), Self::Integer(r)) => l.cmp(r),
+            (Self::Integer(l), Self::List(r)) => std::slice::from_ref(&Self::Integer(*l)).cmp(&r[..]),
+            (Self::List(l), Self::Integer(r)) => l[..].cmp(std::slice::from_ref(&Self::Integer(*r))),
+            // Slices compare element by element, then by length, which is just what's needed
+            (Self::List(l), Self::List(r)) => l.cmp(r),
         }
     }
 }
 
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+// Puts the value into an already sorted list of values, returning where it went
+fn insert_sorted(values: &mut Vec<Value>, value: Value) -> usize {
+    let index = values.partition_point(|v| *v < value);
+    values.insert(index, value);
+    index
+}
+
 fn parse(s: &str) -> Vec<(Value, Value)> {
     s.replace("\r\n", "\n")
         .split("\n\n")
         .map(|lines| {
-            lines.split_once('\n').map(|(l_s, r_s)| {
-                let (_, l) = parser::list(l_s).unwrap_or_else(|e| panic!("Error {e:?} in '{l_s}"));
-                let (_, r) = parser::list(r_s).unwrap_or_else(|e| panic!("Error {e:?} in '{r_s}"));
+            lines.trim().split_once('\n').map(|(l_s, r_s)| {
+                let l = Value::from_str(l_s).unwrap_or_else(|e| panic!("{e} in '{l_s}'"));
+                let r = Value::from_str(r_s).unwrap_or_else(|e| panic!("{e} in '{r_s}'"));
                 (l, r)
             })
         })
@@ -94,40 +156,28 @@ fn part1_evaluate(packets: &[(Value, Value)]) -> usize {
     packets
         .iter()
         .enumerate()
-        .map(|(pos, (l, r))| if l.compare(r) == IsLower::Left { pos + 1 } else { 0 })
+        .map(|(pos, (l, r))| if l < r { pos + 1 } else { 0 })
         .sum::<usize>()
 }
 
 fn part2_evaluate(packets: Vec<(Value, Value)>) -> usize {
-    let recv_packets = packets
+    let mut all_packets = packets
         .into_iter()
         .flat_map(|(l, r)| [l, r])
         .collect::<Vec<_>>();
-    let dividers = vec![
-        Value::List(vec![Value::List(vec![Value::from(2)])]),
-        Value::List(vec![Value::List(vec![Value::from(6)])]),
-    ];
-
-    let all_packets = recv_packets
-        .iter()
-        .chain(dividers.iter())
-        .sorted_by(|l, r| match l.compare(r) {
-            IsLower::Left => Ordering::Less,
-            IsLower::Equal => Ordering::Equal,
-            IsLower::Right => Ordering::Greater,
-        })
-        .collect::<Vec<_>>();
+    all_packets.sort();
 
-    let p1 = 1 + all_packets
+    // The second divider sorts after the first, so inserting it doesn't move the first
+    let dividers = [packet!([[2]]), packet!([[6]])];
+    dividers
+        .map(|divider| insert_sorted(&mut all_packets, divider) + 1)
         .iter()
-        .position(|i| i.compare(&dividers[0]) == IsLower::Equal)
-        .unwrap();
-    let p2 = 1 + all_packets
-        .iter()
-        .position(|i| i.compare(&dividers[1]) == IsLower::Equal)
-        .unwrap();
+        .product()
+}
 
-    p1 * p2
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = Value::from_str(s);
 }
 
 #[cfg(test)]
@@ -156,29 +206,101 @@ const TEST_INPUT_STRING: &str = "\
 [1,[2,[3,[4,[5,6,7]]]],8,9]
 [1,[2,[3,[4,[5,6,0]]]],8,9]";
 
+#[cfg(test)]
 #[test]
 fn test_compare() {
-    assert_eq!(Value::from(vec![1, 1, 3, 1, 1]).compare(&Value::from(vec![1, 1, 5, 1, 1])), IsLower::Left);
-    assert_eq!(Value::from(vec![7, 7, 7, 7]).compare(&Value::from(vec![7, 7, 7])), IsLower::Right);
-    assert_eq!(Value::from(vec![]).compare(&Value::from(vec![3])), IsLower::Left);
+    assert!(Value::from(vec![1, 1, 3, 1, 1]) < Value::from(vec![1, 1, 5, 1, 1]));
+    assert!(Value::from(vec![7, 7, 7, 7]) > Value::from(vec![7, 7, 7]));
+    assert!(Value::from(vec![]) < Value::from(vec![3]));
+    assert_eq!(packet!(2), packet!([[2]]));
+    assert!(packet!([[4, 4], 4, 4]) < packet!([[4, 4], 4, 4, 4]));
+    assert!(packet!([[[]]]) > packet!([[]]));
 }
 
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    for line in TEST_INPUT_STRING.lines().filter(|l| !l.is_empty()) {
+        assert_eq!(Value::from_str(line).unwrap().to_string(), line);
+    }
+    assert_eq!(packet!([1, [2, [3, [4, [5, 6, 7]]]], 8, 9]).to_string(), "[1,[2,[3,[4,[5,6,7]]]],8,9]");
+    assert_eq!(packet!([]).to_string(), "[]");
+    assert_eq!(Value::from_str("17").map(|v| v.to_string()), Ok("17".to_owned()));
+
+    let error = |s: &str| Value::from_str(s).unwrap_err().to_string();
+    assert_eq!(error("[1,2"), "Unexpected end of input at column 5");
+    assert_eq!(error("[1,x]"), "Unexpected 'x' at column 4");
+    assert_eq!(error("[[1],[2,]]"), "Unexpected ']' at column 9");
+    assert_eq!(error("[1,[x]]"), "Unexpected 'x' at column 5");
+    assert_eq!(error("[,1]"), "Unexpected ',' at column 2");
+    assert_eq!(error("[1]]"), "Unexpected ']' at column 4");
+    assert_eq!(error(""), "Unexpected end of input at column 1");
+}
+
+#[cfg(test)]
+#[test]
+fn test_insert_sorted() {
+    let mut packets = [packet!([3]), packet!([1, 2]), packet!([[1], 5])].to_vec();
+    packets.sort();
+    assert_eq!(insert_sorted(&mut packets, packet!([[2]])), 2);
+    assert_eq!(insert_sorted(&mut packets, packet!([])), 0);
+    let sorted = packets.iter().map(Value::to_string).collect::<Vec<_>>();
+    assert_eq!(sorted, ["[]", "[1,2]", "[[1],5]", "[[2]]", "[3]"]);
+}
+
+#[cfg(test)]
 #[test]
 fn test_part1() {
     let packets = parse(TEST_INPUT_STRING);
     assert_eq!(part1_evaluate(&packets), 13);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     let packets = parse(TEST_INPUT_STRING);
     assert_eq!(part2_evaluate(packets), 140);
 }
 
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(&parse(input_string)));
+    let part2_answer = profile::part("part 2", || part2_evaluate(parse(input_string)));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let packets = parse(input_string);
-    let part1_answer = part1_evaluate(&packets);
-    let part2_answer = part2_evaluate(packets);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 5198, part2_answer, part2_answer == 22_344))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn value() -> impl Strategy<Value = Value> {
+        let leaf = (0usize..100).prop_map(Value::Integer);
+        leaf.prop_recursive(4, 32, 5, |inner| prop::collection::vec(inner, 0..5).prop_map(Value::List))
+    }
+
+    proptest! {
+        #[test]
+        fn value_round_trips(v in value()) {
+            let s = v.to_string();
+            prop_assert_eq!(Value::from_str(&s).map(|v| v.to_string()), Ok(s));
+        }
+
+        #[test]
+        fn ordering_is_consistent(a in value(), b in value(), c in value()) {
+            prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+            if a <= b && b <= c {
+                prop_assert!(a <= c);
+            }
+        }
+
+        #[test]
+        fn value_parse_does_not_panic(s in "[\\[\\],0-9]*") {
+            let _ = Value::from_str(&s);
+        }
+    }
+}
@@ -1,105 +1,238 @@
-use super::grid::{Grid, GridCoord};
+use super::profile;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
 enum Material {
+    #[default]
     Air,
     Rock,
     Sand,
 }
 
-impl Default for Material {
-    fn default() -> Self {
-        Material::Air
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+struct Coord {
+    x: i64,
+    y: i64,
+}
+
+impl Coord {
+    fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
     }
 }
 
-fn parse_coord(s: &str) -> Option<GridCoord> {
+const SOURCE: Coord = Coord { x: 500, y: 0 };
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Floor {
+    // Anything falling past the lowest rock keeps going forever
+    Abyss,
+    // A floor at this depth, going on forever in both directions
+    Infinite(i64),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mode {
+    // Every grain falls all the way from its source
+    Restart,
+    // Each grain starts from where the one before it was just before it came to rest, since everything above
+    // there is the same as it was
+    RememberPath,
+}
+
+// Only the rock and sand is stored, so the cave goes on as far as the sand spreads
+struct Cave {
+    cells: HashMap<Coord, Material>,
+    lowest_rock: i64,
+    floor: Floor,
+}
+
+fn parse_coord(s: &str) -> Option<Coord> {
     s.split_once(',')
-        .and_then(|(x, y)| x.parse::<usize>().ok().zip(y.parse::<usize>().ok()))
-        .map(GridCoord::from)
+        .and_then(|(x, y)| x.parse::<i64>().ok().zip(y.parse::<i64>().ok()))
+        .map(|(x, y)| Coord::new(x, y))
 }
 
-fn draw_line(g: &mut Grid<Material>, e0: &GridCoord, e1: &GridCoord) {
-    if e0.x == e1.x {
-        for y in e0.y.min(e1.y)..=e0.y.max(e1.y) {
-            *g.cell_mut(GridCoord::from((e0.x, y))).unwrap() = Material::Rock;
+impl Cave {
+    fn parse(s: &str) -> Self {
+        let mut cave = Cave { cells: HashMap::new(), lowest_rock: i64::MIN, floor: Floor::Abyss };
+        s.lines().for_each(|s| {
+            let vertices = s
+                .split(" -> ")
+                .map(parse_coord)
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_else(|| panic!("Bad line {s}"));
+            vertices[0..]
+                .iter()
+                .zip(vertices[1..].iter())
+                .for_each(|(e0, e1)| cave.draw_line(e0, e1));
+        });
+        cave
+    }
+
+    fn draw_line(&mut self, e0: &Coord, e1: &Coord) {
+        if e0.x != e1.x && e0.y != e1.y {
+            panic!("Diagonal line from {e0:?} to {e1:?}");
         }
-    } else if e0.y == e1.y {
         for x in e0.x.min(e1.x)..=e0.x.max(e1.x) {
-            *g.cell_mut(GridCoord::from((x, e0.y))).unwrap() = Material::Rock;
+            for y in e0.y.min(e1.y)..=e0.y.max(e1.y) {
+                self.cells.insert(Coord::new(x, y), Material::Rock);
+            }
         }
+        self.lowest_rock = self.lowest_rock.max(e0.y).max(e1.y);
     }
-}
 
-fn parse(s: &str) -> (Grid<Material>, usize) {
-    let mut g = Grid::new(2000, 2000);
-    let mut max_y = 0;
-    s.lines().for_each(|s| {
-        let vertices = s
-            .split(" -> ")
-            .map(parse_coord)
-            .collect::<Option<Vec<_>>>()
-            .unwrap_or_else(|| panic!("Bad line {s}"));
-        vertices[0..]
-            .iter()
-            .zip(vertices[1..].iter())
-            .for_each(|(e0, e1)| {
-                max_y = max_y.max(e0.y).max(e1.y);
-                draw_line(&mut g, e0, e1);
-            });
-    });
-    (g, max_y)
-}
-
-fn drop_sand(g: &mut Grid<Material>) -> bool {
-    let mut at = GridCoord::from((500, 0));
-    if *g.cell(at).unwrap() != Material::Air {
-        return false;
-    }
-
-    while at.x != 0 && at.x != g.width() && at.y != g.height() - 1 {
-        let next = GridCoord::from((at.x, at.y + 1));
-        match g.cell(next).unwrap() {
-            Material::Air => {
-                at = next;
-                continue;
-            }
-            Material::Rock | Material::Sand => {
-                let next_left = GridCoord::from((at.x - 1, at.y + 1));
-                if *g.cell(next_left).unwrap() == Material::Air {
-                    at = next_left;
+    fn with_floor(mut self) -> Self {
+        self.floor = Floor::Infinite(self.lowest_rock + 2);
+        self
+    }
+
+    fn material(&self, c: Coord) -> Material {
+        match self.floor {
+            Floor::Infinite(y) if c.y >= y => Material::Rock,
+            _ => self.cells.get(&c).copied().unwrap_or_default(),
+        }
+    }
+
+    // Where a grain of sand goes next, if it can still move
+    fn fall(&self, at: Coord) -> Option<Coord> {
+        [0, -1, 1]
+            .into_iter()
+            .map(|dx| Coord::new(at.x + dx, at.y + 1))
+            .find(|next| self.material(*next) == Material::Air)
+    }
+
+    fn falls_forever(&self, at: Coord) -> bool {
+        self.floor == Floor::Abyss && at.y > self.lowest_rock
+    }
+
+    // Pours sand from each source in turn, skipping any that are blocked, until they all are or a grain falls into
+    // the abyss. Returns how many grains came to rest.
+    fn pour(&mut self, sources: &[Coord], mode: Mode) -> usize {
+        let mut paths = vec![Vec::new(); sources.len()];
+        let mut settled = 0;
+        loop {
+            let mut poured = false;
+            for (index, source) in sources.iter().enumerate() {
+                if self.material(*source) != Material::Air {
                     continue;
                 }
-                let next_right = GridCoord::from((at.x + 1, at.y + 1));
-                if *g.cell(next_right).unwrap() == Material::Air {
-                    at = next_right;
-                    continue;
+                poured = true;
+                let path = &mut paths[index];
+                if mode == Mode::Restart || path.is_empty() {
+                    path.clear();
+                    path.push(*source);
+                }
+                let mut at = *path.last().unwrap();
+                while let Some(next) = self.fall(at) {
+                    if self.falls_forever(next) {
+                        return settled;
+                    }
+                    path.push(next);
+                    at = next;
                 }
-                *g.cell_mut(at).unwrap() = Material::Sand;
-                return true;
+                path.pop();
+                self.cells.insert(at, Material::Sand);
+                settled += 1;
+                if mode == Mode::RememberPath {
+                    for (other, path) in paths.iter_mut().enumerate() {
+                        if other != index {
+                            forget_changed_path(path, at);
+                        }
+                    }
+                }
+            }
+            if !poured {
+                return settled;
             }
         }
     }
-    false
+
+    // With a floor, sand from a single source fills the triangle spreading out below it down to the floor, except for
+    // rock and the cells in its shadow, those with all three cells above them either blocked or outside the triangle.
+    // A triangle `h` rows high holds `h²` cells, so only the blocked cells need visiting, a row at a time.
+    fn floored_sand_count(&self, source: Coord) -> usize {
+        let Floor::Infinite(floor) = self.floor else { panic!("Sand only stops falling with a floor") };
+        if self.material(source) != Material::Air {
+            return 0;
+        }
+        let in_triangle = |c: Coord| c.y >= source.y && c.y < floor && (c.x - source.x).abs() <= c.y - source.y;
+        let mut rocks = BTreeMap::<i64, HashSet<i64>>::new();
+        for c in self.cells.keys().filter(|c| in_triangle(**c)) {
+            rocks.entry(c.y).or_default().insert(c.x);
+        }
+        let is_rock = |c: Coord| rocks.get(&c.y).is_some_and(|row| row.contains(&c.x));
+
+        let mut blocked_count = 0;
+        let mut above = HashSet::new();
+        let mut y = source.y;
+        while y < floor {
+            let shadowed = above
+                .iter()
+                .flat_map(|x: &i64| [x - 1, *x, x + 1])
+                .filter(|x| in_triangle(Coord::new(*x, y)) && !is_rock(Coord::new(*x, y)))
+                .filter(|x| {
+                    (x - 1..=x + 1).all(|above_x| above.contains(&above_x) || !in_triangle(Coord::new(above_x, y - 1)))
+                })
+                .collect::<HashSet<_>>();
+            let mut blocked = shadowed;
+            blocked.extend(rocks.get(&y).into_iter().flatten());
+            blocked_count += blocked.len();
+            above = blocked;
+            y += 1;
+            // Nothing's in shadow until the next rock down
+            if above.is_empty() {
+                match rocks.range(y..).next() {
+                    Some((next_y, _)) => y = *next_y,
+                    None => break,
+                }
+            }
+        }
+        let height = (floor - source.y) as usize;
+        height * height - blocked_count
+    }
+
+    // Goes down a row at a time working out which cells sand can get to from the row above, for any number of sources
+    #[cfg(test)]
+    fn reachable_count(&self, sources: &[Coord]) -> usize {
+        let Floor::Infinite(floor) = self.floor else { panic!("Sand only stops falling with a floor") };
+        let Some(top) = sources.iter().map(|s| s.y).min() else { return 0 };
+        let mut count = 0;
+        let mut row = HashSet::new();
+        for y in top..floor {
+            let mut next = row
+                .iter()
+                .flat_map(|x: &i64| [x - 1, *x, x + 1])
+                .collect::<HashSet<_>>();
+            next.extend(sources.iter().filter(|s| s.y == y).map(|s| s.x));
+            next.retain(|x| self.material(Coord::new(*x, y)) == Material::Air);
+            count += next.len();
+            row = next;
+        }
+        count
+    }
 }
 
-fn part1_evaluate(s: &str) -> usize {
-    let (mut g, _) = parse(s);
-    let mut grains_dropped = 0;
-    while drop_sand(&mut g) {
-        grains_dropped += 1;
+// A grain coming to rest at `at` changes where sand falls from any of the three cells above it, and fills `at`
+// itself, so another source's path is only good up to there
+fn forget_changed_path(path: &mut Vec<Coord>, at: Coord) {
+    if let Some(index) = path
+        .iter()
+        .position(|p| p.y == at.y - 1 && p.x.abs_diff(at.x) <= 1)
+    {
+        path.truncate(index + 1);
     }
-    grains_dropped
+    if let Some(index) = path.iter().position(|p| *p == at) {
+        path.truncate(index);
+    }
+}
+
+fn part1_evaluate(s: &str) -> usize {
+    Cave::parse(s).pour(&[SOURCE], Mode::RememberPath)
 }
 
 fn part2_evaluate(s: &str) -> usize {
-    let (mut g, max_y) = parse(s);
-    draw_line(&mut g, &GridCoord::from((0, max_y + 2)), &GridCoord::from((1999, max_y + 2)));
-    let mut grains_dropped = 0;
-    while drop_sand(&mut g) {
-        grains_dropped += 1;
-    }
-    grains_dropped
+    Cave::parse(s).with_floor().floored_sand_count(SOURCE)
 }
 
 #[cfg(test)]
@@ -112,14 +245,94 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 24);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 93);
 }
 
+#[cfg(test)]
+#[test]
+fn test_modes() {
+    for mode in [Mode::Restart, Mode::RememberPath] {
+        assert_eq!(Cave::parse(TEST_INPUT_STRING).pour(&[SOURCE], mode), 24);
+        assert_eq!(
+            Cave::parse(TEST_INPUT_STRING)
+                .with_floor()
+                .pour(&[SOURCE], mode),
+            93
+        );
+    }
+
+    // Sources can be anywhere, including somewhere rock or other sand covers
+    let sources = [SOURCE, Coord::new(495, 2), Coord::new(-10, -5), Coord::new(498, 5)];
+    let expected = Cave::parse(TEST_INPUT_STRING)
+        .with_floor()
+        .reachable_count(&sources);
+    for mode in [Mode::Restart, Mode::RememberPath] {
+        let mut cave = Cave::parse(TEST_INPUT_STRING).with_floor();
+        assert_eq!(cave.pour(&sources, mode), expected);
+        assert_eq!(
+            cave.cells
+                .values()
+                .filter(|m| **m == Material::Sand)
+                .count(),
+            expected
+        );
+    }
+    assert_eq!(Cave::parse(TEST_INPUT_STRING).pour(&[Coord::new(600, 0)], Mode::Restart), 0);
+    let floored = Cave::parse(TEST_INPUT_STRING).with_floor();
+    assert_eq!(floored.floored_sand_count(Coord::new(498, 5)), 0);
+    assert_eq!(floored.floored_sand_count(Coord::new(495, 2)), floored.reachable_count(&[Coord::new(495, 2)]));
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 683, part2_answer, part2_answer == 28_821))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn line() -> impl Strategy<Value = String> {
+        (0i64..20, 0i64..12, 0i64..6, any::<bool>()).prop_map(|(x, y, length, vertical)| {
+            let (x1, y1) = if vertical { (x, y + length) } else { (x + length, y) };
+            format!("{x},{y} -> {x1},{y1}")
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn pouring_fills_every_reachable_cell(
+            lines in prop::collection::vec(line(), 1..6),
+            sources in prop::collection::vec((0i64..20, -3i64..8), 1..4),
+        ) {
+            let input = lines.join("\n");
+            let sources = sources.into_iter().map(|(x, y)| Coord::new(x, y)).collect::<Vec<_>>();
+            let expected = Cave::parse(&input).with_floor().reachable_count(&sources);
+            for mode in [Mode::Restart, Mode::RememberPath] {
+                prop_assert_eq!(Cave::parse(&input).with_floor().pour(&sources, mode), expected);
+            }
+            prop_assert_eq!(
+                Cave::parse(&input).pour(&sources, Mode::Restart),
+                Cave::parse(&input).pour(&sources, Mode::RememberPath)
+            );
+        }
+
+        #[test]
+        fn closed_form_matches_pouring(lines in prop::collection::vec(line(), 1..6), x in 0i64..20, y in -3i64..8) {
+            let input = lines.join("\n");
+            let source = Coord::new(x, y);
+            let cave = Cave::parse(&input).with_floor();
+            prop_assert_eq!(cave.floored_sand_count(source), cave.reachable_count(&[source]));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Manhattan distance geometry. Rotating by 45° (u = x + y, v = x - y) turns every diamond of cells within some
+//! distance of a centre into an axis-aligned square, and squares are easy to cut up. Only the (u, v) points where u
+//! and v have the same parity are actual cells, so anything that hands back cells works in x and y again.
+
+use super::Coord;
+
+fn floor_half(n: isize) -> isize {
+    n.div_euclid(2)
+}
+
+fn ceil_half(n: isize) -> isize {
+    -(-n).div_euclid(2)
+}
+
+/// An axis-aligned rectangle in rotated space, with inclusive bounds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) struct Square {
+    u: (isize, isize),
+    v: (isize, isize),
+}
+
+impl Square {
+    /// All the cells within `radius` of `centre`
+    pub(super) fn diamond(centre: &Coord, radius: isize) -> Self {
+        let (u, v) = (centre.x + centre.y, centre.x - centre.y);
+        Square { u: (u - radius, u + radius), v: (v - radius, v + radius) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.u.0 > self.u.1 || self.v.0 > self.v.1
+    }
+
+    fn overlaps(&self, other: &Square) -> bool {
+        self.u.0.max(other.u.0) <= self.u.1.min(other.u.1) && self.v.0.max(other.v.0) <= self.v.1.min(other.v.1)
+    }
+
+    // What's left after cutting `other` out, as up to four disjoint pieces: everything left and right of it, then
+    // what's above and below it in between
+    fn subtract(&self, other: &Square) -> Vec<Square> {
+        if !self.overlaps(other) {
+            return vec![*self];
+        }
+        let middle = (self.u.0.max(other.u.0), self.u.1.min(other.u.1));
+        [
+            Square { u: (self.u.0, other.u.0 - 1), v: self.v },
+            Square { u: (other.u.1 + 1, self.u.1), v: self.v },
+            Square { u: middle, v: (self.v.0, other.v.0 - 1) },
+            Square { u: middle, v: (other.v.1 + 1, self.v.1) },
+        ]
+        .into_iter()
+        .filter(|piece| !piece.is_empty())
+        .collect()
+    }
+
+    // The rows of `bounds` that have at least one cell in this square. Every constraint on x, for a given y, is an
+    // interval whose ends move with y, so the rows where they all overlap are an interval too.
+    fn rows_within(&self, bounds: &Rect) -> Option<(isize, isize)> {
+        let first = [bounds.min.y, ceil_half(self.u.0 - self.v.1), self.u.0 - bounds.max.x, bounds.min.x - self.v.1]
+            .into_iter()
+            .max()
+            .unwrap();
+        let last = [bounds.max.y, floor_half(self.u.1 - self.v.0), bounds.max.x - self.v.0, self.u.1 - bounds.min.x]
+            .into_iter()
+            .min()
+            .unwrap();
+        (first <= last).then_some((first, last))
+    }
+
+    // The cells in row y of both this square and `bounds`
+    fn columns_within(&self, y: isize, bounds: &Rect) -> Option<(isize, isize)> {
+        if y < bounds.min.y || y > bounds.max.y {
+            return None;
+        }
+        let first = (self.u.0 - y).max(self.v.0 + y).max(bounds.min.x);
+        let last = (self.u.1 - y).min(self.v.1 + y).min(bounds.max.x);
+        (first <= last).then_some((first, last))
+    }
+}
+
+/// An ordinary rectangle of cells, with inclusive bounds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) struct Rect {
+    pub(super) min: Coord,
+    pub(super) max: Coord,
+}
+
+impl Rect {
+    // The smallest square in rotated space holding the whole rectangle
+    fn enclosing_square(&self) -> Square {
+        Square {
+            u: (self.min.x + self.min.y, self.max.x + self.max.y),
+            v: (self.min.x - self.max.y, self.max.x - self.min.y),
+        }
+    }
+}
+
+/// The cells of a rectangle that none of a set of squares cover, kept as disjoint squares.
+#[derive(Debug)]
+pub(super) struct Region {
+    bounds: Rect,
+    pieces: Vec<Square>,
+}
+
+impl Region {
+    pub(super) fn uncovered(bounds: Rect, covered: &[Square]) -> Self {
+        let mut pieces = vec![bounds.enclosing_square()];
+        for square in covered {
+            pieces = pieces
+                .iter()
+                .flat_map(|piece| piece.subtract(square))
+                .collect();
+        }
+        // Some of what's left may be in the corners of the enclosing square, outside the rectangle
+        pieces.retain(|piece| piece.rows_within(&bounds).is_some());
+        Region { bounds, pieces }
+    }
+
+    /// How many of the cells in row y are uncovered
+    pub(super) fn count_in_row(&self, y: isize) -> usize {
+        self.pieces
+            .iter()
+            .filter_map(|piece| piece.columns_within(y, &self.bounds))
+            .map(|(first, last)| (last - first + 1) as usize)
+            .sum()
+    }
+
+    /// Every uncovered cell, a piece at a time and in row order within each piece
+    pub(super) fn cells(&self) -> impl Iterator<Item = Coord> + '_ {
+        self.pieces.iter().flat_map(move |piece| {
+            let (first_row, last_row) = piece.rows_within(&self.bounds).unwrap();
+            (first_row..=last_row).flat_map(move |y| {
+                let (first, last) = piece.columns_within(y, &self.bounds).unwrap();
+                (first..=last).map(move |x| Coord { x, y })
+            })
+        })
+    }
+}
@@ -1,6 +1,13 @@
+use super::profile;
+use geometry::{Rect, Region, Square};
+use std::collections::HashSet;
+use std::fmt::Display;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
+mod geometry;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub(crate) struct Coord {
     pub(crate) x: isize,
     pub(crate) y: isize,
@@ -18,7 +25,7 @@ impl From<(isize, isize)> for Coord {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 struct Sensor {
     location: Coord,
     nearest_beacon: Coord,
@@ -29,15 +36,18 @@ impl Sensor {
         self.location.x.abs_diff(self.nearest_beacon.x) + self.location.y.abs_diff(self.nearest_beacon.y)
     }
 
-    fn scanned_points(&self, row: isize) -> (Option<RangeInclusive<isize>>, Option<isize>) {
-        let maybe_beacon = (self.nearest_beacon.y == row).then_some(self.nearest_beacon.x);
-        let centre_x = self.location.x;
-        let vertical_distance = self.location.y.abs_diff(row);
-        if vertical_distance > self.distance_to_nearest_beacon() {
-            return (None, maybe_beacon);
-        }
-        let x_extent = (self.distance_to_nearest_beacon() - vertical_distance) as isize;
-        (Some(centre_x - x_extent..=centre_x + x_extent), maybe_beacon)
+    fn coverage(&self) -> Square {
+        Square::diamond(&self.location, self.distance_to_nearest_beacon() as isize)
+    }
+}
+
+impl Display for Sensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Sensor at x={}, y={}: closest beacon is at x={}, y={}",
+            self.location.x, self.location.y, self.nearest_beacon.x, self.nearest_beacon.y
+        )
     }
 }
 
@@ -74,87 +84,59 @@ fn parse(s: &str) -> Vec<Sensor> {
         .unwrap_or_else(|e| panic!("Bad parse - {e}"))
 }
 
-#[allow(clippy::reversed_empty_ranges)]
-fn get_covered_ranges(
-    sensors: &Vec<Sensor>,
-    row: isize,
-    range: &RangeInclusive<isize>,
-    out: &mut Vec<RangeInclusive<isize>>,
-) {
-    out.clear();
-    for s in sensors {
-        let (covered_points, _) = s.scanned_points(row);
-        if let Some(mut covered_points) = covered_points {
-            covered_points = *covered_points.start().max(range.start())..=*covered_points.end().min(range.end());
-            for r in out.iter_mut() {
-                if covered_points.start().max(r.start()) <= covered_points.end().min(r.end()) {
-                    covered_points = (*covered_points.start()).min(*r.start())..=(*covered_points.end()).max(*r.end());
-                    *r = 1..=0;
-                }
-            }
-            out.push(covered_points);
-        }
-    }
-    out.retain(|r| !r.is_empty());
+// Anywhere no sensor covers, since a beacon there would have been the nearest one to some sensor
+fn candidate_beacons(sensors: &[Sensor], bounds: Rect) -> Vec<Coord> {
+    let covered = sensors.iter().map(Sensor::coverage).collect::<Vec<_>>();
+    Region::uncovered(bounds, &covered).cells().collect()
 }
 
-#[allow(clippy::reversed_empty_ranges)]
 fn part1_evaluate(s: &str, row: isize) -> usize {
     let sensors = parse(s);
 
-    let mut all_covered_points = Vec::<RangeInclusive<isize>>::new();
-    let mut beacons = Vec::new();
-    for s in sensors {
-        let (covered_points, maybe_beacon) = s.scanned_points(row);
-        if let Some(mut covered_points) = covered_points {
-            for r in &mut all_covered_points {
-                if covered_points.start().max(r.start()) <= covered_points.end().min(r.end()) {
-                    covered_points = (*covered_points.start()).min(*r.start())..=(*covered_points.end()).max(*r.end());
-                    *r = 1..=0;
-                }
-            }
-            all_covered_points.push(covered_points);
-        }
-        if let Some(beacon) = maybe_beacon {
-            beacons.push(beacon);
-        }
-    }
-
-    for beacon in beacons {
-        let mut right = Option::None;
-        for r in &mut all_covered_points {
-            if r.contains(&beacon) {
-                let left = *r.start()..=beacon - 1;
-                right = Some(beacon + 1..=*r.end());
-                *r = left;
-            }
-        }
-        if let Some(right) = right {
-            all_covered_points.push(right);
-        }
-    }
-
-    all_covered_points
-        .into_iter()
-        .map(|r| if r.is_empty() { 0 } else { r.end() - r.start() + 1 })
-        .sum::<isize>() as usize
+    // Wide enough to hold everything the sensors cover in any row
+    let reach = |sensor: &Sensor| sensor.distance_to_nearest_beacon() as isize;
+    let min_x = sensors
+        .iter()
+        .map(|s| s.location.x - reach(s))
+        .min()
+        .unwrap();
+    let max_x = sensors
+        .iter()
+        .map(|s| s.location.x + reach(s))
+        .max()
+        .unwrap();
+    let bounds = Rect { min: (min_x, row).into(), max: (max_x, row).into() };
+
+    let covered = sensors.iter().map(Sensor::coverage).collect::<Vec<_>>();
+    let covered_count = (max_x - min_x + 1) as usize - Region::uncovered(bounds, &covered).count_in_row(row);
+    // Beacons are always covered by the sensors that found them, but there's obviously a beacon there
+    let beacons_in_row = sensors
+        .iter()
+        .filter(|s| s.nearest_beacon.y == row)
+        .map(|s| s.nearest_beacon)
+        .collect::<HashSet<_>>()
+        .len();
+    covered_count - beacons_in_row
 }
 
 fn part2_evaluate(s: &str, coord_range: RangeInclusive<isize>) -> usize {
     let sensors = parse(s);
-
-    let mut all_covered_points = Vec::<RangeInclusive<isize>>::new();
-    for y in coord_range.clone() {
-        get_covered_ranges(&sensors, y, &coord_range, &mut all_covered_points);
-        if all_covered_points.len() == 2 {
-            let missing_x = all_covered_points[0]
-                .start()
-                .max(all_covered_points[1].start())
-                - 1;
-            return ((missing_x * 4_000_000) + y) as usize;
-        }
+    let bounds = Rect {
+        min: (*coord_range.start(), *coord_range.start()).into(),
+        max: (*coord_range.end(), *coord_range.end()).into(),
+    };
+    match candidate_beacons(&sensors, bounds)[..] {
+        [beacon] => (beacon.x * 4_000_000 + beacon.y) as usize,
+        ref candidates => panic!("Expected exactly one place for the beacon, found {candidates:?}"),
     }
-    0
+}
+
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = s
+        .lines()
+        .map(Sensor::from_str)
+        .collect::<Result<Vec<_>, _>>();
 }
 
 #[cfg(test)]
@@ -179,14 +161,70 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING, 10), 26);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING, 0..=20), 56_000_011);
 }
 
+#[cfg(test)]
+#[test]
+fn test_geometry() {
+    let sensors = parse(TEST_INPUT_STRING);
+    let covered = sensors.iter().map(Sensor::coverage).collect::<Vec<_>>();
+    let is_covered = |c: &Coord| {
+        sensors
+            .iter()
+            .any(|s| s.location.x.abs_diff(c.x) + s.location.y.abs_diff(c.y) <= s.distance_to_nearest_beacon())
+    };
+
+    // Including a rectangle well outside where the sensors are
+    for (min, max) in [((0, 0), (20, 20)), ((-8, -3), (30, 25)), ((-20, 40), (-10, 45)), ((5, 10), (5, 10))] {
+        let bounds = Rect { min: min.into(), max: max.into() };
+        let region = Region::uncovered(bounds, &covered);
+        let mut cells = region.cells().collect::<Vec<_>>();
+        cells.sort();
+        let mut expected = (min.0..=max.0)
+            .flat_map(|x| (min.1..=max.1).map(move |y| Coord::from((x, y))))
+            .filter(|c| !is_covered(c))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(cells, expected);
+        for y in min.1..=max.1 {
+            assert_eq!(region.count_in_row(y), expected.iter().filter(|c| c.y == y).count());
+        }
+    }
+
+    let bounds = Rect { min: (0, 0).into(), max: (20, 20).into() };
+    assert_eq!(candidate_beacons(&sensors, bounds), [Coord::from((14, 11))]);
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string, 2_000_000));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string, 0..=4_000_000));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string, 2_000_000);
-    let part2_answer = part2_evaluate(input_string, 0..=4_000_000);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 6_425_133, part2_answer, part2_answer == 10_996_191_429_555))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn sensor_round_trips(sx: isize, sy: isize, bx: isize, by: isize) {
+            let sensor = Sensor { location: (sx, sy).into(), nearest_beacon: (bx, by).into() };
+            prop_assert_eq!(Sensor::from_str(&sensor.to_string()), Ok(sensor));
+        }
+
+        #[test]
+        fn sensor_parse_does_not_panic(s in "\\PC*") {
+            let _ = Sensor::from_str(&s);
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use super::profile;
 use itertools::Itertools;
 use ndarray::Array2;
 use sscanf::sscanf;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BinaryHeap};
 use std::str::FromStr;
+use tracing::{debug, instrument, trace};
 
 #[derive(Debug)]
 struct Valve {
@@ -31,23 +33,31 @@ fn chars_to_id(id0: char, id1: char) -> usize {
     ((id0 as usize) << 8) | (id1 as usize)
 }
 
-#[allow(dead_code)]
 fn id_as_string(id: usize) -> String {
     [((id >> 8) as u8) as char, (id as u8) as char].iter().collect::<String>()
 }
 
-fn parse(s: &str) -> Vec<Valve> {
+fn try_parse(s: &str) -> Result<Vec<Valve>, String> {
     let mut valves = s
         .lines()
-        .map(|s| Valve::from_str(s).unwrap_or_else(|e| panic!("{s}: {e}")))
-        .sorted_by_key(|v| v.id)
-        .collect::<Vec<_>>();
+        .map(|s| Valve::from_str(s).map_err(|e| format!("{s}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    valves.sort_by_key(|v| v.id);
+    if valves.len() > MAX_VALVES {
+        return Err(format!("{} valves is too many, at most {MAX_VALVES} are supported", valves.len()));
+    }
 
     // Remap valve IDs into indices
-    let valve_ids = valves.iter().map(|v| v.id).sorted().collect::<Vec<_>>();
+    let valve_ids = valves.iter().map(|v| v.id).collect::<Vec<_>>();
     for v in &mut valves {
         for dest in &mut v.destinations {
-            *dest = (valve_ids.iter().position(|id| id == &dest.0).unwrap(), 1);
+            let index = valve_ids
+                .iter()
+                .position(|id| id == &dest.0)
+                .ok_or_else(|| {
+                    format!("Valve {} leads to missing valve {}", id_as_string(v.id), id_as_string(dest.0))
+                })?;
+            *dest = (index, 1);
         }
     }
 
@@ -85,184 +95,181 @@ fn parse(s: &str) -> Vec<Valve> {
             .collect();
     }
 
-    valves
+    Ok(valves)
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-struct State {
-    total_acc_flow: usize,
-    time: usize,
-    valve_id: usize,
-    valves_on: u64,
+fn parse(s: &str) -> Vec<Valve> {
+    try_parse(s).unwrap_or_else(|e| panic!("{e}"))
 }
 
-impl State {
-    fn is_valve_on(&self, id: usize) -> bool {
-        (self.valves_on & (1 << id)) != 0
+const MAX_VALVES: usize = 256;
+
+/// A set of valves by index, for up to `MAX_VALVES` valves.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+struct ValveSet([u64; MAX_VALVES / 64]);
+
+impl ValveSet {
+    fn contains(&self, id: usize) -> bool {
+        (self.0[id / 64] & (1 << (id % 64))) != 0
     }
-    fn turn_valve_on(&mut self, id: usize) {
-        self.valves_on |= 1 << id;
+    fn with(mut self, id: usize) -> Self {
+        self.0[id / 64] |= 1 << (id % 64);
+        self
     }
-}
-
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Notice that the we flip the ordering on costs.
-        // In case of a tie we compare positions - this step is necessary
-        // to make implementations of `PartialEq` and `Ord` consistent.
-        self.total_acc_flow
-            .cmp(&other.total_acc_flow)
-            .then_with(|| self.time.cmp(&other.time))
+    fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
     }
 }
 
-// `PartialOrd` needs to be implemented as well.
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Agent {
+    valve_id: usize,
+    time: usize,
 }
 
-fn part1_evaluate(s: &str) -> usize {
-    let valves = parse(s);
-
-    let usable_valves = valves.iter().filter(|v| v.flow_rate > 0).count();
-
-    let mut heap = BinaryHeap::new();
-    let mut max_flows = BTreeMap::new();
-
-    // We're at `start`, with a zero flow
-    heap.push(State { total_acc_flow: 0, time: 0, valve_id: 0, valves_on: 0 });
-    while let Some(State { total_acc_flow, time, valve_id: current_valve, valves_on }) = heap.pop() {
-        // If all valves are on, we're done...
-        if valves_on.count_ones() as usize == usable_valves {
-            continue;
-        }
-        if total_acc_flow < *max_flows.get(&valves_on).unwrap_or(&0) {
-            continue;
-        }
-
-        // For each node we can reach, see if we can find a way with
-        // a higher flow going through this node
-        for (next_valve, distance) in &valves[current_valve].destinations {
-            let mut next = State { total_acc_flow, time: time + *distance, valve_id: *next_valve, valves_on };
-
-            if !next.is_valve_on(*next_valve) && next.time < 30 {
-                next.time += 1;
-                next.total_acc_flow += valves[*next_valve].flow_rate * (30 - next.time);
-                next.turn_valve_on(*next_valve);
-                if next.total_acc_flow > *max_flows.get(&next.valves_on).unwrap_or(&0) {
-                    heap.push(next);
-                    max_flows.insert(next.valves_on, next.total_acc_flow);
-                }
-            }
-        }
-    }
-    *max_flows.values().max().unwrap_or(&0)
+// One valve being opened, linked back to the step before it so that schedules can be rebuilt without every state
+// carrying its own copy
+#[derive(Debug, Copy, Clone)]
+struct Step {
+    previous: Option<usize>,
+    agent: usize,
+    valve_id: usize,
+    time: usize,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-struct State2 {
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct State {
     total_acc_flow: usize,
-    my_valve_id: usize,
-    my_time: usize,
-    ele_valve_id: usize,
-    ele_time: usize,
-    valves_on: u64,
+    agents: Vec<Agent>,
+    valves_on: ValveSet,
+    last_step: Option<usize>,
 }
 
-fn is_valve_on(valves_on: u64, id: usize) -> bool {
-    (valves_on & (1 << id)) != 0
-}
-fn turn_valve_on(valves_on: u64, id: usize) -> u64 {
-    valves_on | 1 << id
+impl State {
+    fn earliest_time(&self) -> usize {
+        self.agents.iter().map(|a| a.time).min().unwrap_or(0)
+    }
 }
 
-impl Ord for State2 {
+impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        // Notice that the we flip the ordering on costs.
-        // In case of a tie we compare positions - this step is necessary
-        // to make implementations of `PartialEq` and `Ord` consistent.
+        // The heap pops the state with the most flow so far first, and of those the one furthest through its time
         self.total_acc_flow
             .cmp(&other.total_acc_flow)
-            .then_with(|| {
-                self.my_time
-                    .min(self.ele_time)
-                    .cmp(&other.my_time.min(other.ele_time))
-            })
+            .then_with(|| self.earliest_time().cmp(&other.earliest_time()))
     }
 }
 
 // `PartialOrd` needs to be implemented as well.
-impl PartialOrd for State2 {
+impl PartialOrd for State {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-fn part2_evaluate(s: &str) -> usize {
-    let valves = parse(s);
+/// The best total flow, and for each agent the valves it opens in order with the minute each is opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Plan {
+    total_flow: usize,
+    schedules: Vec<Vec<(String, usize)>>,
+}
 
+// All the agents start at the first valve. At each step the agent who's been busy for the least time (that can
+// still get somewhere useful) heads off to open another valve, and states are pruned when some other order of
+// events has already opened the same set of valves for more flow.
+#[instrument(skip(valves))]
+fn plan(valves: &[Valve], agents: usize, time_limit: usize) -> Plan {
     let usable_valves = valves.iter().filter(|v| v.flow_rate > 0).count();
 
     let mut heap = BinaryHeap::new();
     let mut max_flows = BTreeMap::new();
+    let mut steps = Vec::<Step>::new();
+    let mut best = (0, None);
 
     // We're at `start`, with a zero flow
-    heap.push(State2 { total_acc_flow: 0, my_valve_id: 0, ele_valve_id: 0, my_time: 0, ele_time: 0, valves_on: 0 });
+    heap.push(State {
+        total_acc_flow: 0,
+        agents: vec![Agent { valve_id: 0, time: 0 }; agents],
+        valves_on: ValveSet::default(),
+        last_step: None,
+    });
     while let Some(current_state) = heap.pop() {
-        if current_state.valves_on.count_ones() as usize == usable_valves {
+        // If all valves are on, we're done...
+        if current_state.valves_on.len() == usable_valves {
             continue;
         }
         if current_state.total_acc_flow < *max_flows.get(&current_state.valves_on).unwrap_or(&0) {
             continue;
         }
+        trace!(state = ?current_state, "expanding");
 
         // For each node we can reach, see if we can find a way with
         // a higher flow going through this node
-        if current_state.my_time <= current_state.ele_time {
-            for (next_valve, distance) in &valves[current_state.my_valve_id].destinations {
-                let next_my_time = current_state.my_time + *distance + 1;
-                let next_valves_on = turn_valve_on(current_state.valves_on, *next_valve);
+        let agent_order = (0..agents).sorted_by_key(|a| current_state.agents[*a].time);
+        for agent in agent_order {
+            let Agent { valve_id, time } = current_state.agents[agent];
+            let mut can_move = false;
+            for (next_valve, distance) in &valves[valve_id].destinations {
+                let next_time = time + *distance + 1;
+                if current_state.valves_on.contains(*next_valve) || next_time >= time_limit {
+                    continue;
+                }
+                can_move = true;
+                let next_valves_on = current_state.valves_on.with(*next_valve);
                 let next_total_acc_flow =
-                    current_state.total_acc_flow + valves[*next_valve].flow_rate * (26 - next_my_time);
-                if !is_valve_on(current_state.valves_on, *next_valve)
-                    && next_my_time < 26
-                    && next_total_acc_flow > *max_flows.get(&next_valves_on).unwrap_or(&0)
-                {
-                    heap.push(State2 {
-                        total_acc_flow: next_total_acc_flow,
-                        my_valve_id: *next_valve,
-                        my_time: next_my_time,
-                        valves_on: next_valves_on,
-                        ..current_state
+                    current_state.total_acc_flow + valves[*next_valve].flow_rate * (time_limit - next_time);
+                if next_total_acc_flow > *max_flows.get(&next_valves_on).unwrap_or(&0) {
+                    steps.push(Step {
+                        previous: current_state.last_step,
+                        agent,
+                        valve_id: *next_valve,
+                        time: next_time,
                     });
-                    max_flows.insert(next_valves_on, next_total_acc_flow);
-                };
-            }
-        } else {
-            for (next_valve, distance) in &valves[current_state.ele_valve_id].destinations {
-                let next_ele_time = current_state.ele_time + *distance + 1;
-                let next_valves_on = turn_valve_on(current_state.valves_on, *next_valve);
-                let next_total_acc_flow =
-                    current_state.total_acc_flow + valves[*next_valve].flow_rate * (26 - next_ele_time);
-                if !is_valve_on(current_state.valves_on, *next_valve)
-                    && next_ele_time < 26
-                    && next_total_acc_flow > *max_flows.get(&next_valves_on).unwrap_or(&0)
-                {
-                    heap.push(State2 {
+                    let mut next_agents = current_state.agents.clone();
+                    next_agents[agent] = Agent { valve_id: *next_valve, time: next_time };
+                    heap.push(State {
                         total_acc_flow: next_total_acc_flow,
-                        ele_valve_id: *next_valve,
-                        ele_time: next_ele_time,
-                        valves_on: turn_valve_on(current_state.valves_on, *next_valve),
-                        ..current_state
+                        agents: next_agents,
+                        valves_on: next_valves_on,
+                        last_step: Some(steps.len() - 1),
                     });
                     max_flows.insert(next_valves_on, next_total_acc_flow);
+                    if next_total_acc_flow > best.0 {
+                        best = (next_total_acc_flow, Some(steps.len() - 1));
+                    }
                 }
             }
+            if can_move {
+                break;
+            }
         }
     }
-    *max_flows.values().max().unwrap_or(&0)
+
+    let mut schedules = vec![vec![]; agents];
+    let mut step = best.1;
+    while let Some(index) = step {
+        let Step { previous, agent, valve_id, time } = steps[index];
+        schedules[agent].push((id_as_string(valves[valve_id].id), time));
+        step = previous;
+    }
+    schedules.iter_mut().for_each(|schedule| schedule.reverse());
+    debug!(best = best.0, states = max_flows.len(), "search complete");
+    Plan { total_flow: best.0, schedules }
+}
+
+#[instrument(skip(s))]
+fn part1_evaluate(s: &str) -> usize {
+    plan(&parse(s), 1, 30).total_flow
+}
+
+#[instrument(skip(s))]
+fn part2_evaluate(s: &str) -> usize {
+    plan(&parse(s), 2, 26).total_flow
+}
+
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = try_parse(s);
 }
 
 #[cfg(test)]
@@ -283,14 +290,75 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 1651);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 1707);
 }
 
+#[cfg(test)]
+#[test]
+fn test_plan() {
+    let valves = parse(TEST_INPUT_STRING);
+    let schedule = |steps: &[(&str, usize)]| {
+        steps
+            .iter()
+            .map(|(v, t)| (v.to_string(), *t))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        plan(&valves, 1, 30),
+        Plan {
+            total_flow: 1651,
+            schedules: vec![schedule(&[("DD", 2), ("BB", 5), ("JJ", 9), ("HH", 17), ("EE", 21), ("CC", 24)])]
+        }
+    );
+    let two_agents = plan(&valves, 2, 26);
+    assert_eq!(two_agents.total_flow, 1707);
+    let mut opened = two_agents.schedules.concat();
+    opened.sort();
+    assert_eq!(opened, schedule(&[("BB", 7), ("CC", 9), ("DD", 2), ("EE", 11), ("HH", 7), ("JJ", 3)]));
+
+    // More agents than useful valves just leaves some of them idle
+    let many_agents = plan(&valves, 8, 26);
+    assert_eq!(many_agents.schedules.len(), 8);
+    assert!(many_agents.total_flow >= two_agents.total_flow);
+    assert_eq!(plan(&valves, 1, 2).total_flow, 0);
+    assert_eq!(plan(&valves, 1, 3).total_flow, 20);
+}
+
+#[cfg(test)]
+#[test]
+fn test_valve_set() {
+    let set = ValveSet::default().with(3).with(64).with(200);
+    assert!(set.contains(3) && set.contains(64) && set.contains(200));
+    assert!(!set.contains(4) && !set.contains(0) && !set.contains(128));
+    assert_eq!(set.len(), 3);
+    assert_eq!(set.with(64), set);
+
+    // Bigger inputs are turned away rather than overflowing the set
+    let names = ('A'..='Z')
+        .cartesian_product('A'..='Z')
+        .take(MAX_VALVES + 1);
+    let valves = names
+        .map(|(c0, c1)| format!("Valve {c0}{c1} has flow rate=1; tunnel leads to valve AA"))
+        .join("\n");
+    assert_eq!(try_parse(&valves).unwrap_err(), "257 valves is too many, at most 256 are supported");
+    let first = valves.lines().take(MAX_VALVES).join("\n");
+    assert_eq!(try_parse(&first).map(|valves| valves.len()), Ok(MAX_VALVES));
+    assert_eq!(
+        try_parse("Valve AA has flow rate=0; tunnel leads to valve BB").unwrap_err(),
+        "Valve AA leads to missing valve BB"
+    );
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 2330, part2_answer, part2_answer == 2675))
 }
@@ -1,58 +1,188 @@
-use once_cell::sync::OnceCell;
-struct Shape {
-    shape: [u8; 4],
+use super::profile;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::{debug, instrument, trace};
+
+/// A rock, as a bitmask per row from the bottom up, with bit x set if the rock covers column x from its left edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shape {
+    rows: Vec<u64>,
+    width: usize,
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    // Drawn as in the puzzle, top row first, with `#` for rock and `.` for space
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows = s
+            .lines()
+            .rev()
+            .map(|line| {
+                line.chars()
+                    .enumerate()
+                    .try_fold(0u64, |row, (x, c)| match c {
+                        '#' if x < 64 => Ok(row | 1 << x),
+                        '#' => Err(format!("Shape is wider than 64 columns: {line}")),
+                        '.' => Ok(row),
+                        c => Err(format!("Bad character {c} in shape")),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if rows.is_empty() {
+            return Err("Shape has no rows".to_owned());
+        }
+        if rows.contains(&0) {
+            return Err(format!("Shape has an empty row:\n{s}"));
+        }
+        let width = rows
+            .iter()
+            .map(|row| 64 - row.leading_zeros() as usize)
+            .max()
+            .unwrap();
+        if rows.iter().fold(0, |columns, row| columns | row) != u64::MAX >> (64 - width) {
+            return Err(format!("Shape has an empty column:\n{s}"));
+        }
+        Ok(Shape { rows, width })
+    }
+}
+
+pub fn parse_shapes(s: &str) -> Result<Vec<Shape>, String> {
+    let shapes = s
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|shape| !shape.is_empty())
+        .map(Shape::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    if shapes.is_empty() {
+        return Err("No shapes".to_owned());
+    }
+    Ok(shapes)
+}
+
+pub const PUZZLE_SHAPES: &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
+
+/// The shapes that fall in turn, how wide the chamber is, and where each rock appears relative to the left wall
+/// and the top of the tower.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chamber {
+    shapes: Vec<Shape>,
     width: usize,
-    height: usize,
+    spawn_x: usize,
+    spawn_y: usize,
 }
 
-const SPACE_WIDTH: usize = 7;
+impl Chamber {
+    pub fn new(shapes: Vec<Shape>, width: usize, spawn_x: usize, spawn_y: usize) -> Result<Self, String> {
+        if width == 0 || width > 64 {
+            return Err(format!("Chamber width {width} must be between 1 and 64"));
+        }
+        if let Some(shape) = shapes.iter().find(|shape| spawn_x + shape.width > width) {
+            return Err(format!("A shape {} wide doesn't fit at x = {spawn_x} in a chamber {width} wide", shape.width));
+        }
+        Ok(Chamber { shapes, width, spawn_x, spawn_y })
+    }
 
-impl Shape {
-    fn shapes() -> &'static [Shape; 5] {
-        static INSTANCE: OnceCell<[Shape; 5]> = OnceCell::new();
-        INSTANCE.get_or_init(|| {
-            let dash: Shape = Shape { shape: [0b1111_0000, 0, 0, 0], width: 4, height: 1 };
-            let cross: Shape = Shape { shape: [0b0100_0000, 0b1110_0000, 0b0100_0000, 0], width: 3, height: 3 };
-            let l: Shape = Shape { shape: [0b1110_0000, 0b0010_0000, 0b0010_0000, 0], width: 3, height: 3 };
-            let pipe: Shape =
-                Shape { shape: [0b1000_0000, 0b1000_0000, 0b1000_0000, 0b1000_0000], width: 1, height: 4 };
-            let block: Shape = Shape { shape: [0b1100_0000, 0b1100_0000, 0, 0], width: 2, height: 2 };
+    pub fn puzzle() -> Self {
+        Chamber::new(parse_shapes(PUZZLE_SHAPES).unwrap(), 7, 2, 3).unwrap()
+    }
 
-            [dash, cross, l, pipe, block]
-        })
+    // The height of the tower once this many rocks have fallen. Once the rocks and the gas come back round to a state
+    // seen before, with the same top of the tower, the tower grows by the same amount every time round the cycle.
+    #[instrument(skip(self, gas))]
+    pub fn height_after(&self, gas: &str, rock_count: usize) -> Result<usize, String> {
+        let mut sim = Sim::new(self, gas)?;
+        let mut seen = HashMap::new();
+        let mut rocks = 0;
+        while rocks < rock_count {
+            if let Some((previous_rocks, previous_height)) = seen.insert(sim.state(), (rocks, sim.height())) {
+                let cycle_length = rocks - previous_rocks;
+                let growth_per_cycle = sim.height() - previous_height;
+                let cycle_count = (rock_count - rocks) / cycle_length;
+                debug!(rocks, cycle_length, growth_per_cycle, cycle_count, "found cycle");
+                for _ in 0..(rock_count - rocks) % cycle_length {
+                    sim.drop_rock();
+                }
+                return Ok(sim.height() + cycle_count * growth_per_cycle);
+            }
+            sim.drop_rock();
+            rocks += 1;
+        }
+        Ok(sim.height())
     }
 }
 
-struct Sim {
-    space: Vec<u8>,
+// Everything that determines how the tower grows from here: which rock and gas jet are next, and the cells near the
+// top that falling rocks could still reach, as masks per row down from the top
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct State {
+    shape_index: usize,
+    gas_index: usize,
+    reachable: Vec<u64>,
+}
+
+struct Sim<'a> {
+    chamber: &'a Chamber,
+    space: Vec<u64>,
     shape_index: usize,
-    gas: String,
+    gas: &'a [u8],
     gas_index: usize,
 }
 
-impl Sim {
-    fn new(g: &str) -> Self {
-        Self { space: Vec::with_capacity(50_000_000), shape_index: 0, gas: g.to_owned(), gas_index: 0 }
+impl<'a> Sim<'a> {
+    fn new(chamber: &'a Chamber, gas: &'a str) -> Result<Self, String> {
+        let gas = gas.trim();
+        if gas.is_empty() {
+            return Err("No gas jets".to_owned());
+        }
+        if let Some(c) = gas.chars().find(|c| *c != '<' && *c != '>') {
+            return Err(format!("Bad character {c} in gas jets"));
+        }
+        Ok(Self { chamber, space: Vec::new(), shape_index: 0, gas: gas.as_bytes(), gas_index: 0 })
+    }
+    fn height(&self) -> usize {
+        self.space.len()
     }
     fn can_move_rock_to(&self, x: usize, y: usize, shape: &Shape) -> bool {
-        (0..shape.height)
-            .all(|index| y + index >= self.space.len() || (shape.shape[index] >> x) & self.space[y + index] == 0)
+        shape
+            .rows
+            .iter()
+            .enumerate()
+            .all(|(index, row)| y + index >= self.space.len() || (row << x) & self.space[y + index] == 0)
     }
     fn place_rock(&mut self, x: usize, y: usize, shape: &Shape) {
-        let new_max_y = self.space.len().max(y + shape.height);
-        self.space.reserve(shape.height);
-        while self.space.len() < new_max_y {
-            self.space.push(0);
-        }
-        (0..shape.height).for_each(|index| self.space[y + index] |= shape.shape[index] >> x);
+        let new_max_y = self.space.len().max(y + shape.rows.len());
+        self.space.resize(new_max_y, 0);
+        shape
+            .rows
+            .iter()
+            .enumerate()
+            .for_each(|(index, row)| self.space[y + index] |= row << x);
     }
     fn drop_rock(&mut self) {
-        let shape = &Shape::shapes()[self.shape_index];
-        let mut x = 2usize;
-        let mut y = self.space.len() + 3;
-        let max_x = SPACE_WIDTH - Shape::shapes()[self.shape_index].width;
+        let shape = &self.chamber.shapes[self.shape_index];
+        let mut x = self.chamber.spawn_x;
+        let mut y = self.space.len() + self.chamber.spawn_y;
+        let max_x = self.chamber.width - shape.width;
         loop {
-            let gas_move = self.gas.as_bytes()[self.gas_index];
+            let gas_move = self.gas[self.gas_index];
             let new_x = if gas_move == b'<' { x.saturating_sub(1) } else { (x + 1).min(max_x) };
             self.gas_index = (self.gas_index + 1) % self.gas.len();
             if self.can_move_rock_to(new_x, y, shape) {
@@ -63,78 +193,63 @@ impl Sim {
                 y = new_y;
             } else {
                 self.place_rock(x, y, shape);
+                trace!(shape = self.shape_index, x, y, height = self.space.len(), "rock came to rest");
                 break;
             }
         }
-        self.shape_index = (self.shape_index + 1) % Shape::shapes().len();
+        self.shape_index = (self.shape_index + 1) % self.chamber.shapes.len();
     }
-    #[allow(dead_code)]
-    fn draw(&self) {
-        for y in (self.space.len().saturating_sub(10)..self.space.len()).rev() {
-            let line = (0..7)
-                .map(|x| if self.space[y] & (0b1000_0000 >> x) == 0 { '.' } else { '#' })
-                .collect::<String>();
-            println!("{line}");
-            if y == 0 {
-                println!("=======");
+    // Flood fill down and sideways from the empty row above the tower. Rocks only ever move down and sideways, so
+    // nothing below the cells found here can affect the tower any more.
+    fn state(&self) -> State {
+        let full = u64::MAX >> (64 - self.chamber.width);
+        let mut reachable = vec![full];
+        for row in self.space.iter().rev() {
+            let above = *reachable.last().unwrap();
+            let mut cells = above & !row;
+            loop {
+                let spread = (cells | cells << 1 | cells >> 1) & full & !row;
+                if spread == cells {
+                    break;
+                }
+                cells = spread;
+            }
+            if cells == 0 {
+                break;
             }
+            reachable.push(cells);
         }
+        State { shape_index: self.shape_index, gas_index: self.gas_index, reachable }
     }
-}
-
-impl PartialEq for Sim {
-    fn eq(&self, other: &Self) -> bool {
-        self.gas_index == other.gas_index
-            && self.shape_index == other.shape_index
-            && self.space[self.space.len() - 10..] == other.space[other.space.len() - 10..]
+    #[cfg(test)]
+    fn draw(&self) -> String {
+        let mut lines = (self.space.len().saturating_sub(10)..self.space.len())
+            .rev()
+            .map(|y| {
+                (0..self.chamber.width)
+                    .map(|x| if self.space[y] & (1 << x) == 0 { '.' } else { '#' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        if self.space.len() <= 10 {
+            lines.push("=".repeat(self.chamber.width));
+        }
+        lines.join("\n")
     }
 }
 
+#[instrument(skip(s))]
 fn part1_evaluate(s: &str) -> usize {
-    // evaluate(s, 2022)
-    let mut sim = Sim::new(s.trim());
-    for _ in 0..2022 {
-        sim.drop_rock();
-    }
-    sim.space.len()
+    Chamber::puzzle()
+        .height_after(s, 2022)
+        .unwrap_or_else(|e| panic!("{e}"))
 }
 
+#[instrument(skip(s))]
 fn part2_evaluate(s: &str) -> usize {
-    let rock_count = 1_000_000_000_000usize;
-
-    let mut sim1 = Sim::new(s.trim());
-    let mut sim2 = Sim::new(s.trim());
-    let mut first_match = None;
-    let mut second_match = None;
-    let mut height_at_first_match = 0;
-    let mut height_at_second_match = 0;
-    for i in 0..1_000_000 {
-        sim1.drop_rock();
-        sim2.drop_rock();
-        sim2.drop_rock();
-        if sim1 == sim2 {
-            if first_match.is_none() {
-                first_match = Some(i);
-                height_at_first_match = sim1.space.len();
-            } else if second_match.is_none() {
-                second_match = Some(i);
-                height_at_second_match = sim1.space.len();
-            } else {
-                break;
-            }
-        }
-    }
-
-    let cycle_length = second_match.unwrap() - first_match.unwrap();
-    let cycle_count = (rock_count - first_match.unwrap()) / cycle_length;
-    let remnant_after = (rock_count - first_match.unwrap()) % cycle_length;
-    let rocks_per_cycle = height_at_second_match - height_at_first_match;
-
-    sim1 = Sim::new(s.trim());
-    for _ in 0..first_match.unwrap() + remnant_after {
-        sim1.drop_rock();
-    }
-    sim1.space.len() + (cycle_count * rocks_per_cycle)
+    Chamber::puzzle()
+        .height_after(s, 1_000_000_000_000)
+        .unwrap_or_else(|e| panic!("{e}"))
 }
 
 #[cfg(test)]
@@ -145,14 +260,72 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 3068);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 1_514_285_714_288);
 }
 
+#[cfg(test)]
+#[test]
+fn test_shapes() {
+    let chamber = Chamber::puzzle();
+    assert_eq!(chamber.shapes.len(), 5);
+    assert_eq!(chamber.shapes[2], Shape { rows: vec![0b111, 0b100, 0b100], width: 3 });
+    assert_eq!(chamber.shapes[3], Shape { rows: vec![1, 1, 1, 1], width: 1 });
+    assert!("#.\n.#".parse::<Shape>().is_ok());
+    assert!(".#\n.#".parse::<Shape>().is_err());
+    assert!("#\n\n#".parse::<Shape>().is_err());
+    assert!("#x".parse::<Shape>().is_err());
+    assert!(Chamber::new(chamber.shapes.clone(), 7, 4, 3).is_err());
+    assert!(Chamber::new(chamber.shapes.clone(), 65, 2, 3).is_err());
+
+    let wide = "#".repeat(64).parse::<Shape>().unwrap();
+    assert_eq!(wide, Shape { rows: vec![u64::MAX], width: 64 });
+    let wide_chamber = Chamber::new(vec![wide], 64, 0, 3).unwrap();
+    assert_eq!(wide_chamber.height_after("<>", 10), Ok(10));
+    assert!("".parse::<Shape>().is_err());
+    assert_eq!(chamber.height_after("", 10), Err("No gas jets".to_owned()));
+    assert_eq!(chamber.height_after("<>\n", 0), Ok(0));
+    assert!(chamber.height_after("<x>", 10).is_err());
+
+    let mut sim = Sim::new(&chamber, TEST_INPUT_STRING).unwrap();
+    sim.drop_rock();
+    sim.drop_rock();
+    assert_eq!(sim.draw(), "...#...\n..###..\n...#...\n..####.\n=======");
+}
+
+#[cfg(test)]
+#[test]
+fn test_extrapolation() {
+    // Other chambers and shapes should extrapolate to the same heights as simply dropping all the rocks
+    let variants = [
+        (PUZZLE_SHAPES, 7, 2, 3),
+        (PUZZLE_SHAPES, 9, 0, 1),
+        ("###\n\n#\n#\n\n.#\n##", 4, 1, 2),
+        ("#####\n\n#.#\n###", 12, 3, 5),
+    ];
+    for (shapes, width, spawn_x, spawn_y) in variants {
+        let chamber = Chamber::new(parse_shapes(shapes).unwrap(), width, spawn_x, spawn_y).unwrap();
+        for gas in [TEST_INPUT_STRING, "<<>", "><<<>>><><"] {
+            let mut sim = Sim::new(&chamber, gas).unwrap();
+            for rocks in 1..=3000 {
+                sim.drop_rock();
+                if [5, 100, 1234, 3000].contains(&rocks) {
+                    assert_eq!(chamber.height_after(gas, rocks), Ok(sim.height()), "{shapes:?} {width} {gas} {rocks}");
+                }
+            }
+        }
+    }
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 3171, part2_answer, part2_answer == 1_586_627_906_921))
 }
@@ -1,5 +1,7 @@
+use super::profile;
 use itertools::Itertools;
 use ndarray::Array3;
+use std::collections::{HashMap, HashSet};
 
 fn parse(s: &str) -> Vec<(isize, isize, isize)> {
     s.lines()
@@ -17,90 +19,279 @@ fn parse(s: &str) -> Vec<(isize, isize, isize)> {
         .collect::<Vec<_>>()
 }
 
+fn neighbours<T>(
+    cubes: &Array3<T>,
+    (x, y, z): (usize, usize, usize),
+) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+    ADJACENCIES.iter().filter_map(move |(dx, dy, dz)| {
+        let adjacent = (x.checked_add_signed(*dx)?, y.checked_add_signed(*dy)?, z.checked_add_signed(*dz)?);
+        cubes.get(adjacent).map(|_| adjacent)
+    })
+}
+
+// Recolours every cube of colour `from` connected to `start`, returning how many there were
+fn fill(cubes: &mut Array3<CubeType>, start: (usize, usize, usize), from: CubeType, to: CubeType) -> usize {
+    let mut to_check = vec![start];
+    cubes[start] = to;
+    let mut count = 1;
+    while let Some(cube) = to_check.pop() {
+        for adjacent in neighbours(cubes, cube).collect::<Vec<_>>() {
+            if cubes[adjacent] == from {
+                cubes[adjacent] = to;
+                to_check.push(adjacent);
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
 type CubeType = u8;
 const INTERNAL: CubeType = 0;
 const SOLID: CubeType = 1;
 const EXTERNAL: CubeType = 2;
 
-fn evaluate(s: &str) -> (usize, usize) {
-    let mut positions = parse(s);
-
-    let adjacencies = [(-1, 0, 0), (1, 0, 0), (0, -1, 0), (0, 1, 0), (0, 0, -1), (0, 0, 1)];
-
-    let Some((x_min,x_max)) = positions.iter().map(|(x, _, _)| x).minmax().into_option() else { panic!("Bad minmax on x co-ordinates!!!")};
-    let Some((y_min,y_max)) = positions.iter().map(|(_, y, _)| y).minmax().into_option() else { panic!("Bad minmax on y co-ordinates!!!")};
-    let Some((z_min,z_max)) = positions.iter().map(|(_, _, z)| z).minmax().into_option() else { panic!("Bad minmax on z co-ordinates!!!")};
-
-    // We want at least one layer of blank cubes surrounding the filled cubes,
-    // so normalize (x,y,z) co-ordinates to start at (1,1,1)
-    let shape =
-        ((*x_max - *x_min + 1) as usize + 2, (*y_max - *y_min + 1) as usize + 2, (*z_max - *z_min + 1) as usize + 2);
-    let x_offset = 1 - *x_min;
-    let y_offset = 1 - *y_min;
-    let z_offset = 1 - *z_min;
-    let x_range = 0..shape.0 as isize;
-    let y_range = 0..shape.1 as isize;
-    let z_range = 0..shape.2 as isize;
-
-    let mut cubes = Array3::<CubeType>::from_elem(shape, INTERNAL);
-
-    for (x, y, z) in &mut positions {
-        *x += x_offset;
-        *y += y_offset;
-        *z += z_offset;
-        cubes[[*x as usize, *y as usize, *z as usize]] = SOLID;
-    }
+const ADJACENCIES: [(isize, isize, isize); 6] = [(-1, 0, 0), (1, 0, 0), (0, -1, 0), (0, 1, 0), (0, 0, -1), (0, 0, 1)];
+
+struct Droplet {
+    cubes: Array3<CubeType>,
+    // Add this to a cube's puzzle co-ordinates to get its index in `cubes`
+    offset: (isize, isize, isize),
+    positions: Vec<(usize, usize, usize)>,
+}
+
+impl Droplet {
+    fn new(mut positions: Vec<(isize, isize, isize)>) -> Self {
+        let Some((x_min, x_max)) = positions.iter().map(|(x, _, _)| x).minmax().into_option() else {
+            panic!("Bad minmax on x co-ordinates!!!")
+        };
+        let Some((y_min, y_max)) = positions.iter().map(|(_, y, _)| y).minmax().into_option() else {
+            panic!("Bad minmax on y co-ordinates!!!")
+        };
+        let Some((z_min, z_max)) = positions.iter().map(|(_, _, z)| z).minmax().into_option() else {
+            panic!("Bad minmax on z co-ordinates!!!")
+        };
+
+        // We want at least one layer of blank cubes surrounding the filled cubes,
+        // so normalize (x,y,z) co-ordinates to start at (1,1,1)
+        let shape = (
+            (*x_max - *x_min + 1) as usize + 2,
+            (*y_max - *y_min + 1) as usize + 2,
+            (*z_max - *z_min + 1) as usize + 2,
+        );
+        let offset = (1 - *x_min, 1 - *y_min, 1 - *z_min);
+
+        let mut cubes = Array3::<CubeType>::from_elem(shape, INTERNAL);
+        for (x, y, z) in &mut positions {
+            *x += offset.0;
+            *y += offset.1;
+            *z += offset.2;
+            cubes[[*x as usize, *y as usize, *z as usize]] = SOLID;
+        }
 
-    let naive_faces_count = positions
-        .iter()
-        .map(|(x, y, z)| {
-            let adjacent_count = adjacencies
+        // Now start colouring cubes from (0,0,0)
+        let mut droplet = Droplet {
+            cubes,
+            offset,
+            positions: positions
                 .iter()
-                .filter(|(dx, dy, dz)| {
-                    let adjacent = (x + *dx, y + *dy, z + *dz);
-                    cubes[[adjacent.0 as usize, adjacent.1 as usize, adjacent.2 as usize]] == INTERNAL
-                })
-                .count();
-            adjacent_count
-        })
-        .sum();
-
-    // Now start colouring cubes from (0,0,0)
-    let mut to_check = Vec::with_capacity(shape.0 * shape.1 * shape.2);
-    cubes[[0, 0, 0]] = EXTERNAL;
-    to_check.push((0, 0, 0));
-    while !to_check.is_empty() {
-        let (x, y, z) = to_check.pop().unwrap();
-        for (dx, dy, dz) in adjacencies.iter() {
-            let adjacent =
-                (dx.saturating_add_unsigned(x), dy.saturating_add_unsigned(y), dz.saturating_add_unsigned(z));
-            if x_range.contains(&adjacent.0)
-                && y_range.contains(&adjacent.1)
-                && z_range.contains(&adjacent.2)
-                && cubes[[adjacent.0 as usize, adjacent.1 as usize, adjacent.2 as usize]] == INTERNAL
-            {
-                cubes[[adjacent.0 as usize, adjacent.1 as usize, adjacent.2 as usize]] = EXTERNAL;
-                to_check.push((adjacent.0 as usize, adjacent.1 as usize, adjacent.2 as usize));
+                .map(|(x, y, z)| (*x as usize, *y as usize, *z as usize))
+                .collect(),
+        };
+        fill(&mut droplet.cubes, (0, 0, 0), INTERNAL, EXTERNAL);
+        droplet
+    }
+
+    fn neighbours(&self, cube: (usize, usize, usize)) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        neighbours(&self.cubes, cube)
+    }
+
+    fn surface_area(&self) -> usize {
+        self.positions
+            .iter()
+            .map(|cube| {
+                self.neighbours(*cube)
+                    .filter(|adjacent| self.cubes[*adjacent] != SOLID)
+                    .count()
+            })
+            .sum()
+    }
+
+    fn exterior_surface_area(&self) -> usize {
+        self.positions
+            .iter()
+            .map(|cube| {
+                self.neighbours(*cube)
+                    .filter(|adjacent| self.cubes[*adjacent] == EXTERNAL)
+                    .count()
+            })
+            .sum()
+    }
+
+    // The volume of each pocket of air trapped inside the droplet, largest first. Filling each pocket as it's found
+    // means every cube is only looked at once more.
+    fn air_pockets(&self) -> Vec<usize> {
+        let mut cubes = self.cubes.clone();
+        let mut volumes = Vec::new();
+        for cube in ndarray::indices(cubes.dim()) {
+            if cubes[cube] == INTERNAL {
+                volumes.push(fill(&mut cubes, cube, INTERNAL, EXTERNAL));
             }
         }
+        volumes.sort_unstable_by(|a, b| b.cmp(a));
+        volumes
+    }
+
+    // Two triangles for every face between the droplet and the outside air, wound anticlockwise when seen from
+    // outside. Vertices are shared between faces and are in the puzzle's co-ordinates, each cube filling the unit
+    // cube above and to the right of its position.
+    fn exterior_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        let mut vertex_ids = HashMap::new();
+        for cube in &self.positions {
+            for (index, (dx, dy, dz)) in ADJACENCIES.iter().enumerate() {
+                let normal = [*dx, *dy, *dz];
+                let adjacent =
+                    (cube.0.wrapping_add_signed(*dx), cube.1.wrapping_add_signed(*dy), cube.2.wrapping_add_signed(*dz));
+                if self.cubes[adjacent] != EXTERNAL {
+                    continue;
+                }
+                // The face is across axis a, spanned by the next two axes round, whose cross product is along a
+                let a = index / 2;
+                let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+                let mut base =
+                    [cube.0 as isize - self.offset.0, cube.1 as isize - self.offset.1, cube.2 as isize - self.offset.2];
+                if normal[a] > 0 {
+                    base[a] += 1;
+                }
+                let mut corners = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(db, dc)| {
+                    let mut corner = base;
+                    corner[b] += db;
+                    corner[c] += dc;
+                    *vertex_ids.entry(corner).or_insert_with(|| {
+                        mesh.vertices.push(corner);
+                        mesh.vertices.len() - 1
+                    })
+                });
+                if normal[a] < 0 {
+                    corners.reverse();
+                }
+                mesh.triangles
+                    .push(Triangle { vertices: [corners[0], corners[1], corners[2]], normal });
+                mesh.triangles
+                    .push(Triangle { vertices: [corners[0], corners[2], corners[3]], normal });
+            }
+        }
+        mesh
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Triangle {
+    vertices: [usize; 3],
+    normal: [isize; 3],
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Mesh {
+    vertices: Vec<[isize; 3]>,
+    triangles: Vec<Triangle>,
+}
+
+impl Mesh {
+    fn edges(&self) -> HashSet<(usize, usize)> {
+        self.triangles
+            .iter()
+            .flat_map(|t| [(0, 1), (1, 2), (2, 0)].map(|(i, j)| (t.vertices[i], t.vertices[j])))
+            .map(|(v0, v1)| (v0.min(v1), v0.max(v1)))
+            .collect()
+    }
+
+    // V - E + F, which is 2 for each separate closed surface with no holes through it
+    fn euler_characteristic(&self) -> isize {
+        self.vertices.len() as isize - self.edges().len() as isize + self.triangles.len() as isize
     }
 
-    let interface_faces = cubes
-        .indexed_iter()
-        .filter(|(_, value)| value == &&INTERNAL)
-        .map(|((x, y, z), _)| {
-            adjacencies
+    fn to_obj(&self) -> String {
+        let mut obj = String::new();
+        for [x, y, z] in &self.vertices {
+            obj += &format!("v {x} {y} {z}\n");
+        }
+        for [x, y, z] in ADJACENCIES.map(|(x, y, z)| [x, y, z]) {
+            obj += &format!("vn {x} {y} {z}\n");
+        }
+        for t in &self.triangles {
+            let normal = 1 + ADJACENCIES
                 .iter()
-                .filter(|(dx, dy, dz)| {
-                    let adjacent =
-                        (x.saturating_add_signed(*dx), y.saturating_add_signed(*dy), z.saturating_add_signed(*dz));
-                    cubes.get(adjacent).unwrap() == &SOLID
-                })
-                .count()
-        })
-        .sum::<usize>();
+                .position(|(x, y, z)| [*x, *y, *z] == t.normal)
+                .unwrap();
+            let [v0, v1, v2] = t.vertices.map(|v| v + 1);
+            obj += &format!("f {v0}//{normal} {v1}//{normal} {v2}//{normal}\n");
+        }
+        obj
+    }
+
+    fn to_stl(&self, name: &str) -> String {
+        let mut stl = format!("solid {name}\n");
+        for t in &self.triangles {
+            let [nx, ny, nz] = t.normal;
+            stl += &format!("  facet normal {nx} {ny} {nz}\n    outer loop\n");
+            for v in t.vertices {
+                let [x, y, z] = self.vertices[v];
+                stl += &format!("      vertex {x} {y} {z}\n");
+            }
+            stl += "    endloop\n  endfacet\n";
+        }
+        stl + &format!("endsolid {name}\n")
+    }
+}
+
+/// File formats the outside of the droplet can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    Stl,
+}
+
+impl MeshFormat {
+    /// Picks the format from a file name's extension
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "obj" => Some(MeshFormat::Obj),
+            "stl" => Some(MeshFormat::Stl),
+            _ => None,
+        }
+    }
+}
+
+/// The outside surface of the droplet described by `input`, ready to be written to a file
+pub fn export_mesh(input: &str, format: MeshFormat) -> String {
+    let mesh = Droplet::new(parse(input)).exterior_mesh();
+    match format {
+        MeshFormat::Obj => mesh.to_obj(),
+        MeshFormat::Stl => mesh.to_stl("droplet"),
+    }
+}
+
+/// What's trapped inside the droplet, and what shape its outside is
+pub fn report(input: &str) -> String {
+    let droplet = Droplet::new(parse(input));
+    let pockets = droplet.air_pockets();
+    let mesh = droplet.exterior_mesh();
+    format!(
+        "{} air pockets holding {} cubes of air {pockets:?}, exterior mesh of {} vertices and {} triangles with Euler \
+         characteristic {}",
+        pockets.len(),
+        pockets.iter().sum::<usize>(),
+        mesh.vertices.len(),
+        mesh.triangles.len(),
+        mesh.euler_characteristic()
+    )
+}
 
-    (naive_faces_count, naive_faces_count - interface_faces)
+fn evaluate(s: &str) -> (usize, usize) {
+    let droplet = Droplet::new(parse(s));
+    (droplet.surface_area(), droplet.exterior_surface_area())
 }
 
 #[cfg(test)]
@@ -129,13 +320,99 @@ fn test_part1() {
     assert_eq!(evaluate(TEST_INPUT_STRING2).0, 64);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(evaluate(TEST_INPUT_STRING2).1, 58);
 }
 
+#[cfg(test)]
+#[test]
+fn test_air_pockets() {
+    assert_eq!(Droplet::new(parse(TEST_INPUT_STRING2)).air_pockets(), [1]);
+    assert!(Droplet::new(parse(TEST_INPUT_STRING1))
+        .air_pockets()
+        .is_empty());
+
+    // A hollow 5x5x5 cube with a solid wall through the middle of it
+    let hollow = (0..5)
+        .flat_map(|x| (0..5).flat_map(move |y| (0..5).map(move |z| (x, y, z))))
+        .filter(|&(x, y, z)| [x, y, z].iter().any(|c| *c == 0 || *c == 4) || y == 2)
+        .collect::<Vec<_>>();
+    let droplet = Droplet::new(hollow);
+    assert_eq!(droplet.air_pockets(), [9, 9]);
+    assert_eq!(droplet.surface_area() - droplet.exterior_surface_area(), 2 * 30);
+}
+
+#[cfg(test)]
+#[test]
+fn test_mesh() {
+    let ring = parse("0,0,0\n1,0,0\n2,0,0\n2,1,0\n2,2,0\n1,2,0\n0,2,0\n0,1,0");
+    let cases = [
+        (parse("0,0,0"), 2),
+        (parse(TEST_INPUT_STRING1), 2),
+        (parse(TEST_INPUT_STRING2), 2),
+        // The hole through the middle of a ring makes it a torus
+        (ring, 0),
+    ];
+    for (positions, euler_characteristic) in cases {
+        let droplet = Droplet::new(positions);
+        let mesh = droplet.exterior_mesh();
+        assert_eq!(mesh.triangles.len(), 2 * droplet.exterior_surface_area());
+        assert_eq!(mesh.edges().len() * 2, mesh.triangles.len() * 3);
+        assert_eq!(mesh.euler_characteristic(), euler_characteristic);
+
+        // Consistently wound, so every edge is used once in each direction by the triangles either side of it
+        let directed_edges = mesh
+            .triangles
+            .iter()
+            .flat_map(|t| [(0, 1), (1, 2), (2, 0)].map(|(i, j)| (t.vertices[i], t.vertices[j])))
+            .collect::<Vec<_>>();
+        let unique = directed_edges.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), directed_edges.len());
+        assert!(directed_edges
+            .iter()
+            .all(|(v0, v1)| unique.contains(&(*v1, *v0))));
+
+        // And the winding agrees with the normals, which point out of the droplet
+        for t in &mesh.triangles {
+            let [p0, p1, p2] = t.vertices.map(|v| mesh.vertices[v]);
+            let (e1, e2) = ([0, 1, 2].map(|i| p1[i] - p0[i]), [0, 1, 2].map(|i| p2[i] - p0[i]));
+            let cross = [e1[1] * e2[2] - e1[2] * e2[1], e1[2] * e2[0] - e1[0] * e2[2], e1[0] * e2[1] - e1[1] * e2[0]];
+            assert_eq!(cross, t.normal);
+        }
+    }
+
+    // Cubes touching only along an edge make the real droplet's surface pinch, so all that can be said is that there
+    // are two triangles per face
+    let droplet = Droplet::new(parse(include_str!("./input.txt")));
+    assert_eq!(droplet.exterior_mesh().triangles.len(), 2 * droplet.exterior_surface_area());
+
+    let mesh = Droplet::new(parse("0,0,0")).exterior_mesh();
+    let obj = mesh.to_obj();
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 12);
+    assert!(obj.contains("\nf 4//1 3//1 2//1\n"), "{obj}");
+    let stl = mesh.to_stl("cube");
+    assert!(stl.starts_with("solid cube\n  facet normal -1 0 0\n    outer loop\n      vertex 0 0 1\n"));
+    assert_eq!(stl.matches("endfacet").count(), 12);
+    assert!(stl.ends_with("endsolid cube\n"));
+
+    assert_eq!(export_mesh("0,0,0", MeshFormat::Obj), obj);
+    assert_eq!(MeshFormat::from_path(std::path::Path::new("a/droplet.stl")), Some(MeshFormat::Stl));
+    assert_eq!(MeshFormat::from_path(std::path::Path::new("droplet")), None);
+    assert_eq!(
+        report(TEST_INPUT_STRING2),
+        "1 air pockets holding 1 cubes of air [1], exterior mesh of 60 vertices and 116 triangles with Euler \
+         characteristic 2"
+    );
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    profile::part("parts 1 & 2", || evaluate(input_string))
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let (part1_answer, part2_answer) = evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 3374, part2_answer, part2_answer == 2010))
 }
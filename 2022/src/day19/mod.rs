@@ -1,142 +1,214 @@
+use super::profile;
 use rayon::prelude::*;
-use sscanf::sscanf;
 use std::str::FromStr;
+use tracing::{debug, instrument, trace};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Robot {
-    ore: usize,
-    clay: usize,
-    obsidian: usize,
+/// A robot that produces one unit of `produces` each minute, and what it costs to build, indexed by resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recipe {
+    produces: usize,
+    costs: Vec<usize>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Any number of resources, numbered in the order they're first mentioned. The factory starts with a single robot
+/// producing the first resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Blueprint {
     id: usize,
-    ore: Robot,
-    clay: Robot,
-    obsidian: Robot,
-    geode: Robot,
-    max_needs: Robot,
+    resources: Vec<String>,
+    recipes: Vec<Recipe>,
+    // The most of each resource that can be spent in a minute, since only one robot is built at a time
+    max_needs: Vec<usize>,
 }
 
+impl Blueprint {
+    fn resource(&self, name: &str) -> Option<usize> {
+        self.resources.iter().position(|r| r == name)
+    }
+}
+
+// Parses e.g. "Blueprint 1: Each ore robot costs 4 ore. Each obsidian robot costs 3 ore and 14 clay."
 impl FromStr for Blueprint {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        sscanf!(s, "Blueprint {usize}: Each ore robot costs {usize} ore. Each clay robot costs {usize} ore. Each obsidian robot costs {usize} ore and {usize} clay. Each geode robot costs {usize} ore and {usize} obsidian.")
-            .map_err(|e| e.to_string())
-            .map(|(id, ore_ore,clay_ore,obsidian_ore,obsidian_clay,geode_ore,geode_obsidian)| Blueprint{
-                id,
-                ore:Robot{ore:ore_ore,clay:0,obsidian:0,},
-                clay:Robot{ore:clay_ore,clay:0,obsidian:0,},
-                obsidian:Robot{ore:obsidian_ore,clay:obsidian_clay,obsidian:0,},
-                geode:Robot{ore:geode_ore,clay:0,obsidian:geode_obsidian,},
-                max_needs:Robot{
-                    ore:ore_ore.max(clay_ore).max(obsidian_ore).max(geode_ore),
-                    clay:obsidian_clay,
-                    obsidian:geode_obsidian,
-                },
-        })
+        let (id, recipes) = s
+            .strip_prefix("Blueprint ")
+            .and_then(|s| s.split_once(':'))
+            .ok_or_else(|| format!("Bad blueprint {s}"))?;
+        let id = id.parse::<usize>().map_err(|e| e.to_string())?;
+
+        let mut resources = Vec::<String>::new();
+        let mut resource = |name: &str| {
+            resources.iter().position(|r| r == name).unwrap_or_else(|| {
+                resources.push(name.to_owned());
+                resources.len() - 1
+            })
+        };
+        let recipes = recipes
+            .split('.')
+            .map(str::trim)
+            .filter(|recipe| !recipe.is_empty())
+            .map(|recipe| {
+                let (robot, costs) = recipe
+                    .strip_prefix("Each ")
+                    .and_then(|r| r.split_once(" robot costs "))
+                    .ok_or_else(|| format!("Bad recipe {recipe}"))?;
+                let produces = resource(robot);
+                let costs = costs
+                    .split(" and ")
+                    .map(|cost| {
+                        let (amount, name) = cost
+                            .split_once(' ')
+                            .ok_or_else(|| format!("Bad cost {cost}"))?;
+                        Ok((resource(name), amount.parse::<usize>().map_err(|e| e.to_string())?))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok((produces, costs))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if recipes.is_empty() {
+            return Err(format!("No recipes in {s}"));
+        }
+
+        let recipes = recipes
+            .into_iter()
+            .map(|(produces, named_costs)| {
+                let mut costs = vec![0; resources.len()];
+                for (resource, amount) in named_costs {
+                    costs[resource] += amount;
+                }
+                Recipe { produces, costs }
+            })
+            .collect::<Vec<_>>();
+        let max_needs = (0..resources.len())
+            .map(|r| {
+                recipes
+                    .iter()
+                    .map(|recipe| recipe.costs[r])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        Ok(Blueprint { id, resources, recipes, max_needs })
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct State {
-    ore_robots: usize,
-    clay_robots: usize,
-    obsidian_robots: usize,
-    geode_robots: usize,
-    ore: usize,
-    clay: usize,
-    obsidian: usize,
-    geode: usize,
-}
-
-impl State {
-    fn new() -> Self {
-        State {
-            ore_robots: 1,
-            clay_robots: 0,
-            obsidian_robots: 0,
-            geode_robots: 0,
-            ore: 0,
-            clay: 0,
-            obsidian: 0,
-            geode: 0,
-        }
-    }
+    time_left: usize,
+    robots: Vec<usize>,
+    stock: Vec<usize>,
 }
 
-#[derive(Debug)]
-enum TryToBuild {
-    Ore,
-    Clay,
-    Obsidian,
-    Geode,
+/// The most of the target resource that can be collected, and the build order that does it: the minute each robot
+/// is finished and the resource it produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Plan {
+    score: usize,
+    build_order: Vec<(usize, String)>,
 }
 
-fn timestep(mut time_left: usize, b: &Blueprint, mut state: State, will_build: &TryToBuild) -> usize {
-    loop {
-        if time_left == 0 {
-            return state.geode;
-        }
-        time_left -= 1;
-        let will_construct = match will_build {
-            TryToBuild::Ore => state.ore >= b.ore.ore,
-            TryToBuild::Clay => state.ore >= b.clay.ore,
-            TryToBuild::Obsidian => state.ore >= b.obsidian.ore && state.clay >= b.obsidian.clay,
-            TryToBuild::Geode => state.ore >= b.geode.ore && state.obsidian >= b.geode.obsidian,
-        };
+struct Optimiser<'a> {
+    blueprint: &'a Blueprint,
+    target: usize,
+    time_limit: usize,
+    build_order: Vec<(usize, usize)>,
+    best: usize,
+    best_build_order: Vec<(usize, usize)>,
+}
 
-        state.ore += state.ore_robots;
-        state.clay += state.clay_robots;
-        state.obsidian += state.obsidian_robots;
-        state.geode += state.geode_robots;
+impl Optimiser<'_> {
+    // How long until a robot can be started with the current robots, if it ever can
+    fn wait_for(&self, state: &State, recipe: &Recipe) -> Option<usize> {
+        recipe
+            .costs
+            .iter()
+            .enumerate()
+            .map(|(r, cost)| match (cost.saturating_sub(state.stock[r]), state.robots[r]) {
+                (0, _) => Some(0),
+                (_, 0) => None,
+                (shortfall, robots) => Some(shortfall.div_ceil(robots)),
+            })
+            .try_fold(0, |wait, w| w.map(|w| wait.max(w)))
+    }
 
-        if will_construct {
-            match will_build {
-                TryToBuild::Ore => {
-                    state.ore -= b.ore.ore;
-                    state.ore_robots += 1;
-                }
-                TryToBuild::Clay => {
-                    state.clay_robots += 1;
-                    state.ore -= b.clay.ore;
-                }
-                TryToBuild::Obsidian => {
-                    state.obsidian_robots += 1;
-                    state.ore -= b.obsidian.ore;
-                    state.clay -= b.obsidian.clay;
-                }
-                TryToBuild::Geode => {
-                    state.geode_robots += 1;
-                    state.ore -= b.geode.ore;
-                    state.obsidian -= b.geode.obsidian;
-                }
-            }
-            break;
-        }
+    // Even building a target robot every remaining minute can't collect more than this
+    fn upper_bound(&self, state: &State) -> usize {
+        let t = state.time_left;
+        state.stock[self.target] + state.robots[self.target] * t + t * t.saturating_sub(1) / 2
     }
 
-    let mut max_geodes = state.geode;
-    for try_to_build in [TryToBuild::Ore, TryToBuild::Clay, TryToBuild::Obsidian, TryToBuild::Geode] {
-        match try_to_build {
-            TryToBuild::Ore if state.ore_robots >= b.max_needs.ore => {
+    // Is there any point building another of these robots? Not if the current ones already make as much as could
+    // ever be spent, because only one robot can be built each minute.
+    fn worth_building(&self, state: &State, resource: usize) -> bool {
+        let t = state.time_left;
+        resource == self.target
+            || state.robots[resource] * t + state.stock[resource] < self.blueprint.max_needs[resource] * t
+    }
+
+    // Rather than stepping a minute at a time, branch on which robot to build next and skip ahead to when it's done
+    fn search(&mut self, state: &State) {
+        let idle_score = state.stock[self.target] + state.robots[self.target] * state.time_left;
+        if idle_score > self.best {
+            self.best = idle_score;
+            self.best_build_order = self.build_order.clone();
+        }
+        if self.upper_bound(state) <= self.best {
+            return;
+        }
+        trace!(blueprint = self.blueprint.id, ?state, "expanding");
+        // Later recipes tend to be the more valuable ones, and finding good plans early prunes more
+        for (index, recipe) in self.blueprint.recipes.iter().enumerate().rev() {
+            if !self.worth_building(state, recipe.produces) {
+                continue;
+            }
+            let Some(wait) = self.wait_for(state, recipe) else { continue };
+            // A robot finished in the last minute can't produce anything
+            if wait + 1 >= state.time_left {
                 continue;
             }
-            TryToBuild::Clay if state.clay_robots >= b.max_needs.clay => continue,
-            TryToBuild::Obsidian if state.obsidian_robots >= b.max_needs.obsidian || state.clay_robots == 0 => continue,
-            TryToBuild::Geode if state.obsidian_robots == 0 || state.clay_robots == 0 => continue,
-            _ => max_geodes = max_geodes.max(timestep(time_left, b, state.clone(), &try_to_build)),
+            let elapsed = wait + 1;
+            let mut next = State {
+                time_left: state.time_left - elapsed,
+                robots: state.robots.clone(),
+                stock: state
+                    .stock
+                    .iter()
+                    .zip(&state.robots)
+                    .zip(&recipe.costs)
+                    .map(|((stock, robots), cost)| stock + robots * elapsed - cost)
+                    .collect(),
+            };
+            next.robots[recipe.produces] += 1;
+            self.build_order
+                .push((self.time_limit - next.time_left, index));
+            self.search(&next);
+            self.build_order.pop();
         }
     }
-    max_geodes
+}
+
+#[instrument(skip(b), fields(blueprint = b.id))]
+fn optimise(b: &Blueprint, target: usize, time_limit: usize) -> Plan {
+    let mut robots = vec![0; b.resources.len()];
+    robots[0] = 1;
+    let initial = State { time_left: time_limit, robots, stock: vec![0; b.resources.len()] };
+    let mut optimiser =
+        Optimiser { blueprint: b, target, time_limit, build_order: vec![], best: 0, best_build_order: vec![] };
+    optimiser.search(&initial);
+    let build_order = optimiser
+        .best_build_order
+        .iter()
+        .map(|(minute, recipe)| (*minute, b.resources[b.recipes[*recipe].produces].clone()))
+        .collect();
+    debug!(score = optimiser.best, "optimisation complete");
+    Plan { score: optimiser.best, build_order }
 }
 
 fn simulate(b: &Blueprint, time_allowed: usize) -> usize {
-    let quantity_ore = timestep(time_allowed, b, State::new(), &TryToBuild::Ore);
-    let quantity_clay = timestep(time_allowed, b, State::new(), &TryToBuild::Clay);
-    quantity_ore.max(quantity_clay)
+    let geode = b.resource("geode").expect("No geode robots in blueprint");
+    optimise(b, geode, time_allowed).score
 }
 
 fn parse(s: &str) -> Vec<Blueprint> {
@@ -162,6 +234,14 @@ fn part2_evaluate(s: &str) -> usize {
         .product::<usize>()
 }
 
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = s
+        .lines()
+        .map(Blueprint::from_str)
+        .collect::<Result<Vec<_>, _>>();
+}
+
 #[cfg(test)]
 const TEST_INPUT_STRING: &str = "\
 Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
@@ -170,41 +250,63 @@ Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsid
 #[test]
 fn test_part1() {
     let blueprints = parse(TEST_INPUT_STRING);
-
-    let now = std::time::Instant::now();
-    assert_eq!(dbg!(simulate(&blueprints[0], 24)), 9);
-    let duration = now.elapsed().as_micros();
-    println!("Took {duration} microseconds");
-
-    let now = std::time::Instant::now();
-    assert_eq!(dbg!(simulate(&blueprints[1], 24)), 12);
-    let duration = now.elapsed().as_micros();
-    println!("Took {duration} microseconds");
-
-    let now = std::time::Instant::now();
-    assert_eq!(dbg!(part1_evaluate(TEST_INPUT_STRING)), 33);
-    let duration = now.elapsed().as_micros();
-    println!("Took {duration} microseconds");
+    assert_eq!(simulate(&blueprints[0], 24), 9);
+    assert_eq!(simulate(&blueprints[1], 24), 12);
+    assert_eq!(part1_evaluate(TEST_INPUT_STRING), 33);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     let blueprints = parse(TEST_INPUT_STRING);
+    assert_eq!(simulate(&blueprints[0], 32), 56);
+    assert_eq!(simulate(&blueprints[1], 32), 62);
+}
+
+#[cfg(test)]
+#[test]
+fn test_optimise() {
+    let blueprints = parse(TEST_INPUT_STRING);
+    assert_eq!(blueprints[0].resources, ["ore", "clay", "obsidian", "geode"]);
+    assert_eq!(blueprints[0].recipes[2], Recipe { produces: 2, costs: vec![3, 14, 0, 0] });
+    assert_eq!(blueprints[0].max_needs, [4, 14, 7, 0]);
 
-    // let now = std::time::Instant::now();
-    // assert_eq!(dbg!(simulate(&blueprints[0], 32)), 56);
-    // let duration = now.elapsed().as_micros();
-    // println!("Took {duration} microseconds");
+    let plan = optimise(&blueprints[0], 3, 24);
+    assert_eq!(plan.score, 9);
+    // The build order from the puzzle text
+    let expected = [
+        (3, "clay"),
+        (5, "clay"),
+        (7, "clay"),
+        (11, "obsidian"),
+        (12, "clay"),
+        (15, "obsidian"),
+        (18, "geode"),
+        (21, "geode"),
+    ];
+    assert_eq!(plan.build_order, expected.map(|(minute, robot)| (minute, robot.to_owned())));
+
+    // Any resources, and any of them can be the target
+    let blueprint = "Blueprint 7: Each wood robot costs 1 wood. Each stone robot costs 2 wood. \
+                     Each gold robot costs 3 stone and 1 wood."
+        .parse::<Blueprint>()
+        .unwrap();
+    assert_eq!(blueprint.resources, ["wood", "stone", "gold"]);
+    assert_eq!(optimise(&blueprint, 0, 5).score, 8);
+    assert_eq!(optimise(&blueprint, 2, 3).score, 0);
+    assert!(optimise(&blueprint, 2, 10).score > 0);
+    assert!("Blueprint 1: Each ore robot costs lots of ore."
+        .parse::<Blueprint>()
+        .is_err());
+}
 
-    let now = std::time::Instant::now();
-    assert_eq!(dbg!(simulate(&blueprints[1], 32)), 62);
-    let duration = now.elapsed().as_micros();
-    println!("Took {duration} microseconds");
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
 }
 
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 1962, part2_answer, part2_answer == 88_160))
 }
@@ -1,18 +1,192 @@
-#[derive(Debug, Clone)]
-struct Number {
-    value: i64,
-    id: usize,
-}
+use super::profile;
 
-fn parse(s: &str) -> Vec<Number> {
+const DECRYPTION_KEY: i64 = 811_589_153;
+
+fn parse(s: &str) -> Vec<i64> {
     s.lines()
         .map(str::parse::<i64>)
-        .enumerate()
-        .map(|(id, res)| res.map(|value| Number { value, id }))
         .collect::<Result<Vec<_>, _>>()
         .unwrap_or_else(|_| panic!("Bad input!"))
 }
 
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Node {
+    value: i64,
+    priority: u64,
+    left: usize,
+    right: usize,
+    parent: usize,
+    size: usize,
+}
+
+// The numbers being mixed, as an implicit treap: an in-order walk of the tree gives the current order, and each
+// node knows the size of its subtree, so a number's position can be found by walking up from its node, and the
+// list can be split and joined at any position, all in O(log n). Nodes are indexed by the number's original
+// position, which is how the mixing order refers to them.
+struct MixList {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+impl MixList {
+    fn new(values: &[i64]) -> Self {
+        // Any well-spread priorities will do, so use a fixed xorshift sequence to keep runs repeatable
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let nodes = values
+            .iter()
+            .map(|value| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                Node { value: *value, priority: state, left: NIL, right: NIL, parent: NIL, size: 1 }
+            })
+            .collect();
+        let mut list = MixList { nodes, root: NIL };
+        for id in 0..values.len() {
+            list.root = list.merge(list.root, id);
+        }
+        list
+    }
+
+    fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    fn size(&self, n: usize) -> usize {
+        if n == NIL {
+            0
+        } else {
+            self.nodes[n].size
+        }
+    }
+
+    fn update(&mut self, n: usize) {
+        let Node { left, right, .. } = self.nodes[n];
+        self.nodes[n].size = 1 + self.size(left) + self.size(right);
+        for child in [left, right] {
+            if child != NIL {
+                self.nodes[child].parent = n;
+            }
+        }
+    }
+
+    // Splits the tree rooted at `n` into its first `k` numbers and the rest
+    fn split(&mut self, n: usize, k: usize) -> (usize, usize) {
+        if n == NIL {
+            return (NIL, NIL);
+        }
+        self.nodes[n].parent = NIL;
+        let left_size = self.size(self.nodes[n].left);
+        if k <= left_size {
+            let (l, r) = self.split(self.nodes[n].left, k);
+            self.nodes[n].left = r;
+            self.update(n);
+            (l, n)
+        } else {
+            let (l, r) = self.split(self.nodes[n].right, k - left_size - 1);
+            self.nodes[n].right = l;
+            self.update(n);
+            (n, r)
+        }
+    }
+
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL || b == NIL {
+            let n = if a == NIL { b } else { a };
+            if n != NIL {
+                self.nodes[n].parent = NIL;
+            }
+            return n;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            self.nodes[a].right = self.merge(self.nodes[a].right, b);
+            self.update(a);
+            self.nodes[a].parent = NIL;
+            a
+        } else {
+            self.nodes[b].left = self.merge(a, self.nodes[b].left);
+            self.update(b);
+            self.nodes[b].parent = NIL;
+            b
+        }
+    }
+
+    fn position(&self, id: usize) -> usize {
+        let mut position = self.size(self.nodes[id].left);
+        let mut n = id;
+        while self.nodes[n].parent != NIL {
+            let parent = self.nodes[n].parent;
+            if self.nodes[parent].right == n {
+                position += self.size(self.nodes[parent].left) + 1;
+            }
+            n = parent;
+        }
+        position
+    }
+
+    // Takes a number out of the list and puts it back `shift` places further on, treating the list as circular
+    fn move_by(&mut self, id: usize, shift: i64) {
+        let len = self.len();
+        if len <= 1 {
+            return;
+        }
+        let position = self.position(id);
+        let (before, rest) = self.split(self.root, position);
+        let (node, after) = self.split(rest, 1);
+        let remaining = self.merge(before, after);
+        let new_position = (position as i64 + shift).rem_euclid(len as i64 - 1) as usize;
+        let (before, after) = self.split(remaining, new_position);
+        let before = self.merge(before, node);
+        self.root = self.merge(before, after);
+    }
+
+    fn values(&self) -> Vec<i64> {
+        let mut values = Vec::with_capacity(self.len());
+        let mut stack = vec![];
+        let mut n = self.root;
+        while n != NIL || !stack.is_empty() {
+            while n != NIL {
+                stack.push(n);
+                n = self.nodes[n].left;
+            }
+            n = stack.pop().unwrap();
+            values.push(self.nodes[n].value);
+            n = self.nodes[n].right;
+        }
+        values
+    }
+}
+
+// Mixes the numbers, multiplied by `key`, `rounds` times over, returning them in their final order
+fn mix(numbers: &[i64], key: i64, rounds: usize) -> Vec<i64> {
+    let mut list = MixList::new(&numbers.iter().map(|n| n * key).collect::<Vec<_>>());
+    for _ in 0..rounds {
+        for id in 0..numbers.len() {
+            list.move_by(id, list.nodes[id].value);
+        }
+    }
+    list.values()
+}
+
+fn grove_coordinates(mixed: &[i64]) -> i64 {
+    let zero_pos = mixed.iter().position(|v| *v == 0).unwrap();
+    mixed[(zero_pos + 1000) % mixed.len()]
+        + mixed[(zero_pos + 2000) % mixed.len()]
+        + mixed[(zero_pos + 3000) % mixed.len()]
+}
+
+fn part1_evaluate(s: &str) -> i64 {
+    grove_coordinates(&mix(&parse(s), 1, 1))
+}
+
+fn part2_evaluate(s: &str) -> i64 {
+    grove_coordinates(&mix(&parse(s), DECRYPTION_KEY, 10))
+}
+
+// The original quadratic mixing, kept to check the treap against
+#[cfg(test)]
 fn mix_n<SomeType>(nums: &mut Vec<SomeType>, initial_pos: usize, num_shifts: i64) {
     let size = nums.len() as i64;
     let reduced_shifts = num_shifts % (size - 1);
@@ -28,37 +202,32 @@ fn mix_n<SomeType>(nums: &mut Vec<SomeType>, initial_pos: usize, num_shifts: i64
     }
 }
 
-fn part1_evaluate(s: &str) -> i64 {
-    let original = parse(s);
-    let mut mixed = original.clone();
-    for i in original.iter() {
-        let mix_pos = mixed.iter().position(|n| i.id == n.id).unwrap();
-        mix_n(&mut mixed, mix_pos, i.value);
-    }
-    let zero_pos = mixed.iter().position(|v| v.value == 0).unwrap();
-    mixed[(zero_pos + 1000) % mixed.len()].value
-        + mixed[(zero_pos + 2000) % mixed.len()].value
-        + mixed[(zero_pos + 3000) % mixed.len()].value
-}
-
-fn part2_evaluate(s: &str) -> i64 {
-    let original = parse(s);
-    let mut mixed = original
+#[cfg(test)]
+fn naive_mix(numbers: &[i64], key: i64, rounds: usize) -> Vec<i64> {
+    let mut mixed = numbers
         .iter()
-        .map(|Number { id, value }| Number { id: *id, value: value * 811_589_153 })
+        .map(|n| n * key)
+        .enumerate()
         .collect::<Vec<_>>();
-    for _ in 0..10 {
-        for i in original.iter() {
-            let mix_pos = mixed.iter().position(|n| i.id == n.id).unwrap();
-            let mix_value = mixed[mix_pos].value;
+    for _ in 0..rounds {
+        for id in 0..numbers.len() {
+            let mix_pos = mixed.iter().position(|(i, _)| *i == id).unwrap();
+            let mix_value = mixed[mix_pos].1;
             mix_n(&mut mixed, mix_pos, mix_value);
         }
     }
+    mixed.into_iter().map(|(_, value)| value).collect()
+}
 
-    let zero_pos = mixed.iter().position(|v| v.value == 0).unwrap();
-    mixed[(zero_pos + 1000) % mixed.len()].value
-        + mixed[(zero_pos + 2000) % mixed.len()].value
-        + mixed[(zero_pos + 3000) % mixed.len()].value
+// Mixing treats the list as circular, so compare orders starting from the same number
+#[cfg(test)]
+fn rotated_to(values: &[i64], first: i64) -> Vec<i64> {
+    let start = values.iter().position(|v| *v == first).unwrap();
+    values[start..]
+        .iter()
+        .chain(&values[..start])
+        .copied()
+        .collect()
 }
 
 #[cfg(test)]
@@ -168,14 +337,53 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 3);
 }
 
+#[cfg(test)]
+#[test]
+fn test_treap_mix() {
+    let numbers = parse(TEST_INPUT_STRING);
+    assert_eq!(rotated_to(&mix(&numbers, 1, 1), 1), [1, 2, -3, 4, 0, 3, -2]);
+    for (key, rounds) in [(1, 1), (DECRYPTION_KEY, 10), (-7, 3)] {
+        assert_eq!(rotated_to(&mix(&numbers, key, rounds), 0), rotated_to(&naive_mix(&numbers, key, rounds), 0));
+    }
+
+    let mut list = MixList::new(&(0..1000).collect::<Vec<_>>());
+    assert!((0..1000).all(|id| list.position(id) == id));
+    list.move_by(10, 5);
+    assert_eq!(list.position(10), 15);
+    assert_eq!(list.position(15), 14);
+    list.move_by(10, -16);
+    assert_eq!(list.position(10), 998);
+}
+
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 1_623_178_306);
 }
 
+pub fn solve(input_string: &str) -> (i64, i64) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(i64, bool, i64, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 7228, part2_answer, part2_answer == 4_526_232_706_281))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn treap_mixes_like_naive(mut numbers in prop::collection::vec(-50i64..50, 2..40), key in -3i64..1000, rounds in 1usize..4) {
+            // Mixing needs a 0 to line the results up by, and the puzzle guarantees exactly one
+            numbers.retain(|n| *n != 0);
+            numbers.push(0);
+            prop_assert_eq!(rotated_to(&mix(&numbers, key, rounds), 0), rotated_to(&naive_mix(&numbers, key, rounds), 0));
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use super::profile;
 use std::array::TryFromSliceError;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
@@ -22,33 +25,18 @@ impl Op {
             Op::Equal => (l == r) as i64,
         }
     }
+}
 
-    fn calculate_operand(self, result: i64, left: Option<i64>, right: Option<i64>) -> i64 {
-        match (left, self, right) {
-            (None, Op::Plus, Some(r)) => result - r,
-            (None, Op::Minus, Some(r)) => result + r,
-            (None, Op::Mult, Some(r)) => result / r,
-            (None, Op::Div, Some(r)) => result * r,
-            (None, Op::Equal, Some(r)) => {
-                if result == 1 {
-                    r
-                } else {
-                    panic!("Can't handle inequality")
-                }
-            }
-            (Some(l), Op::Plus, None) => result - l,
-            (Some(l), Op::Minus, None) => l - result,
-            (Some(l), Op::Mult, None) => result / l,
-            (Some(l), Op::Div, None) => l / result,
-            (Some(l), Op::Equal, None) => {
-                if result == 1 {
-                    l
-                } else {
-                    panic!("Can't handle inequality")
-                }
-            }
-            _ => panic!("Bad expression {self:?} with left={left:?}, right={right:?}, result={result}!!!!"),
-        }
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = match self {
+            Op::Plus => '+',
+            Op::Minus => '-',
+            Op::Mult => '*',
+            Op::Div => '/',
+            Op::Equal => '=',
+        };
+        write!(f, "{c}")
     }
 }
 
@@ -83,6 +71,15 @@ impl Value {
         }
     }
 }
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(v) => write!(f, "{v}"),
+            Value::Operation { left_monkey, op, right_monkey } => write!(f, "{left_monkey} {op} {right_monkey}"),
+        }
+    }
+}
+
 impl FromStr for Value {
     type Err = String;
 
@@ -123,13 +120,21 @@ impl FromStr for Id {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(Id(u32::from_be_bytes(
-            s.as_bytes()[0..4]
+            s.as_bytes()
+                .get(0..4)
+                .ok_or_else(|| format!("Bad Id {s}"))?
                 .try_into()
                 .map_err(|e: TryFromSliceError| e.to_string())?,
         )))
     }
 }
 
+impl Display for Id {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0.to_be_bytes()))
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 struct Monkey {
     id: Id,
@@ -137,20 +142,6 @@ struct Monkey {
 }
 
 impl Monkey {
-    fn uses(&self, monkeys: &Monkeys, monkey: Id) -> bool {
-        self.id == monkey
-            || match self.value {
-                Value::Operation { left_monkey, op: _, right_monkey } => {
-                    monkeys
-                        .get_monkey(left_monkey)
-                        .map_or(false, |m| m.uses(monkeys, monkey))
-                        || monkeys
-                            .get_monkey(right_monkey)
-                            .map_or(false, |m| m.uses(monkeys, monkey))
-                }
-                Value::Number(_) => false,
-            }
-    }
     fn get_value(&self, monkeys: &Monkeys) -> i64 {
         self.value.get_value(monkeys)
     }
@@ -180,8 +171,8 @@ impl Monkeys {
     }
 }
 
-fn parse(s: &str) -> Monkeys {
-    Monkeys {
+fn try_parse(s: &str) -> Result<Monkeys, String> {
+    Ok(Monkeys {
         monkeys: s
             .lines()
             .map(|line| {
@@ -192,10 +183,13 @@ fn parse(s: &str) -> Monkeys {
                 let expression = Value::from_str(expression)?;
                 Ok((id, Monkey { id, value: expression }))
             })
-            .collect::<Result<HashMap<_, _>, _>>()
-            .unwrap_or_else(|e: String| panic!("{e}")),
+            .collect::<Result<HashMap<_, _>, String>>()?,
         cached_values: RefCell::new(HashMap::new()),
-    }
+    })
+}
+
+fn parse(s: &str) -> Monkeys {
+    try_parse(s).unwrap_or_else(|e| panic!("{e}"))
 }
 
 fn part1_evaluate(s: &str) -> i64 {
@@ -204,42 +198,253 @@ fn part1_evaluate(s: &str) -> i64 {
     monkeys.get_value(root_id)
 }
 
-fn calculate_human_value(monkeys: &Monkeys, top_monkey: &Monkey, human_id: Id, expected_result: i64) -> i64 {
-    if top_monkey.id == human_id {
-        return expected_result;
-    }
-    let Value::Operation { left_monkey, op, right_monkey } = top_monkey.value else {
-        panic!("top_monkey {top_monkey:?} isn't an expression!");
-    };
-    let left_monkey = monkeys.get_monkey(left_monkey).unwrap();
-    let right_monkey = monkeys.get_monkey(right_monkey).unwrap();
-    let (left_is_human_side, human_side, non_human_side) = if left_monkey.uses(monkeys, human_id) {
-        (true, left_monkey, right_monkey)
-    } else {
-        (false, right_monkey, left_monkey)
-    };
-    let non_human_side_operand = non_human_side.get_value(monkeys);
-    let next_result = if left_is_human_side {
-        op.calculate_operand(expected_result, None, Some(non_human_side_operand))
+/// An exact fraction, always stored in lowest terms with a positive denominator.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
     } else {
-        op.calculate_operand(expected_result, Some(non_human_side_operand), None)
+        gcd(b, a % b)
+    }
+}
+
+impl Rational {
+    const ZERO: Rational = Rational { num: 0, den: 1 };
+    const ONE: Rational = Rational { num: 1, den: 1 };
+
+    fn new(num: i128, den: i128) -> Result<Self, SolveError> {
+        if den == 0 {
+            return Err(SolveError::DivisionByZero);
+        }
+        let g = i128::try_from(gcd(num.unsigned_abs(), den.unsigned_abs())).map_err(|_| SolveError::Overflow)?;
+        let g = if den < 0 { -g } else { g };
+        Ok(Rational { num: num.checked_div(g).ok_or(SolveError::Overflow)?, den: den / g })
+    }
+    fn integer(n: i64) -> Self {
+        Rational { num: n as i128, den: 1 }
+    }
+    fn to_integer(self) -> Option<i64> {
+        (self.den == 1)
+            .then(|| i64::try_from(self.num).ok())
+            .flatten()
+    }
+    // Both are already in lowest terms, so cancelling the common factors first keeps the products as small as they
+    // can be, and anything still too big for an i128 is an error rather than a wrong answer
+    fn perform(self, op: Op, r: Rational) -> Result<Rational, SolveError> {
+        let overflow = |n: Option<i128>| n.ok_or(SolveError::Overflow);
+        let common = |a: i128, b: i128| gcd(a.unsigned_abs(), b.unsigned_abs()) as i128;
+        match op {
+            Op::Plus | Op::Minus => {
+                let g = common(self.den, r.den);
+                let left = overflow(self.num.checked_mul(r.den / g))?;
+                let right = overflow(r.num.checked_mul(self.den / g))?;
+                let num = overflow(match op {
+                    Op::Plus => left.checked_add(right),
+                    _ => left.checked_sub(right),
+                })?;
+                Rational::new(num, overflow(self.den.checked_mul(r.den / g))?)
+            }
+            Op::Mult => {
+                let (g1, g2) = (common(self.num, r.den).max(1), common(r.num, self.den).max(1));
+                Rational::new(
+                    overflow((self.num / g1).checked_mul(r.num / g2))?,
+                    overflow((self.den / g2).checked_mul(r.den / g1))?,
+                )
+            }
+            Op::Div => {
+                if r.num == 0 {
+                    return Err(SolveError::DivisionByZero);
+                }
+                let reciprocal = match r.num.signum() {
+                    1 => Rational { num: r.den, den: r.num },
+                    _ => Rational { num: -r.den, den: overflow(r.num.checked_neg())? },
+                };
+                self.perform(Op::Mult, reciprocal)
+            }
+            Op::Equal => Ok(Rational::integer((self == r) as i64)),
+        }
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum SolveError {
+    UnknownMonkey(Id),
+    Cycle(Id),
+    DivisionByZero,
+    Overflow,
+    NonLinear(String),
+    NoSolution,
+    InfinitelyManySolutions,
+    NoIntegerSolution(Rational),
+}
+
+impl Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::UnknownMonkey(id) => write!(f, "No monkey called {id}"),
+            SolveError::Cycle(id) => write!(f, "Monkey {id} depends on itself"),
+            SolveError::DivisionByZero => write!(f, "Division by zero"),
+            SolveError::Overflow => write!(f, "A number got too big to keep exactly"),
+            SolveError::NonLinear(e) => write!(f, "Can't solve non-linear expression {e}"),
+            SolveError::NoSolution => write!(f, "No solution"),
+            SolveError::InfinitelyManySolutions => write!(f, "Every value is a solution"),
+            SolveError::NoIntegerSolution(r) => write!(f, "The only solution, {r}, isn't an integer"),
+        }
+    }
+}
+
+/// The expression a monkey yells, with `Unknown` standing in for the monkey being solved for. Monkeys listened to by
+/// more than one other monkey share their expression, so this is really a DAG.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Expr {
+    Constant(Rational),
+    Unknown(Id),
+    Operation(Rc<Expr>, Op, Rc<Expr>),
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Constant(r) => write!(f, "{r}"),
+            Expr::Unknown(id) => write!(f, "{id}"),
+            Expr::Operation(l, op, r) => write!(f, "({l} {op} {r})"),
+        }
+    }
+}
+
+// `a * unknown + b`
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct Linear {
+    a: Rational,
+    b: Rational,
+}
+
+impl Expr {
+    // Each monkey's expression is built once, with every subexpression that doesn't involve the unknown folded down
+    // to a constant, and shared by everything that refers to it
+    fn build(
+        monkeys: &Monkeys,
+        id: Id,
+        unknown: Option<Id>,
+        visiting: &mut Vec<Id>,
+        built: &mut HashMap<Id, Rc<Expr>>,
+    ) -> Result<Rc<Expr>, SolveError> {
+        if Some(id) == unknown {
+            return Ok(Rc::new(Expr::Unknown(id)));
+        }
+        if let Some(expr) = built.get(&id) {
+            return Ok(expr.clone());
+        }
+        if visiting.contains(&id) {
+            return Err(SolveError::Cycle(id));
+        }
+        let monkey = monkeys
+            .get_monkey(id)
+            .ok_or(SolveError::UnknownMonkey(id))?;
+        let expr = match monkey.value {
+            Value::Number(n) => Rc::new(Expr::Constant(Rational::integer(n))),
+            Value::Operation { left_monkey, op, right_monkey } => {
+                visiting.push(id);
+                let left = Expr::build(monkeys, left_monkey, unknown, visiting, built)?;
+                let right = Expr::build(monkeys, right_monkey, unknown, visiting, built)?;
+                visiting.pop();
+                Expr::simplify(left, op, right)?
+            }
+        };
+        built.insert(id, expr.clone());
+        Ok(expr)
+    }
+
+    // Folds an operation on two constants down to a constant, and drops identities
+    fn simplify(l: Rc<Expr>, op: Op, r: Rc<Expr>) -> Result<Rc<Expr>, SolveError> {
+        Ok(match (&*l, op, &*r) {
+            (Expr::Constant(a), op, Expr::Constant(b)) => Rc::new(Expr::Constant(a.perform(op, *b)?)),
+            (Expr::Constant(Rational::ZERO), Op::Plus, _) | (Expr::Constant(Rational::ONE), Op::Mult, _) => r,
+            (_, Op::Plus | Op::Minus, Expr::Constant(Rational::ZERO))
+            | (_, Op::Mult | Op::Div, Expr::Constant(Rational::ONE)) => l,
+            _ => Rc::new(Expr::Operation(l, op, r)),
+        })
+    }
+
+    // Shared subexpressions are only worked out once, remembered by where they live
+    fn linear(self: &Rc<Self>, known: &mut HashMap<*const Expr, Linear>) -> Result<Linear, SolveError> {
+        if let Some(linear) = known.get(&Rc::as_ptr(self)) {
+            return Ok(*linear);
+        }
+        let non_linear = || SolveError::NonLinear(self.to_string());
+        let linear = match &**self {
+            Expr::Constant(b) => Linear { a: Rational::ZERO, b: *b },
+            Expr::Unknown(_) => Linear { a: Rational::ONE, b: Rational::ZERO },
+            Expr::Operation(l, op, r) => {
+                let (l, r) = (l.linear(known)?, r.linear(known)?);
+                match op {
+                    Op::Plus | Op::Minus => Linear { a: l.a.perform(*op, r.a)?, b: l.b.perform(*op, r.b)? },
+                    Op::Mult if l.a == Rational::ZERO => {
+                        Linear { a: r.a.perform(Op::Mult, l.b)?, b: r.b.perform(Op::Mult, l.b)? }
+                    }
+                    Op::Mult | Op::Div if r.a == Rational::ZERO => {
+                        Linear { a: l.a.perform(*op, r.b)?, b: l.b.perform(*op, r.b)? }
+                    }
+                    _ => return Err(non_linear()),
+                }
+            }
+        };
+        known.insert(Rc::as_ptr(self), linear);
+        Ok(linear)
+    }
+}
+
+// Finds the value `unknown` must yell for the two sides of `root` to be equal. The unknown can appear any number of
+// times on either side, as long as the equation is linear in it.
+fn solve_for(monkeys: &Monkeys, root: Id, unknown: Id) -> Result<i64, SolveError> {
+    let Some(Value::Operation { left_monkey, right_monkey, .. }) = monkeys.get_monkey(root).map(|m| m.value) else {
+        return Err(SolveError::UnknownMonkey(root));
     };
-    calculate_human_value(monkeys, human_side, human_id, next_result)
+    let mut built = HashMap::new();
+    let left = Expr::build(monkeys, left_monkey, Some(unknown), &mut vec![root], &mut built)?;
+    let right = Expr::build(monkeys, right_monkey, Some(unknown), &mut vec![root], &mut built)?;
+    let mut known = HashMap::new();
+    let (left, right) = (left.linear(&mut known)?, right.linear(&mut known)?);
+    // a * unknown + b = 0
+    let a = left.a.perform(Op::Minus, right.a)?;
+    let b = left.b.perform(Op::Minus, right.b)?;
+    match (a, b) {
+        (Rational::ZERO, Rational::ZERO) => Err(SolveError::InfinitelyManySolutions),
+        (Rational::ZERO, _) => Err(SolveError::NoSolution),
+        (a, b) => {
+            let solution = Rational::ZERO.perform(Op::Minus, b)?.perform(Op::Div, a)?;
+            solution
+                .to_integer()
+                .ok_or(SolveError::NoIntegerSolution(solution))
+        }
+    }
 }
 
 fn part2_evaluate(s: &str) -> i64 {
-    let mut monkeys = parse(s);
+    let monkeys = parse(s);
     let root_id = Id::from_str("root").unwrap_or_else(|e| panic!("{e}"));
     let my_id = Id::from_str("humn").unwrap_or_else(|e| panic!("{e}"));
-    {
-        let root = monkeys.monkeys.get_mut(&root_id).unwrap();
-        let Value::Operation { left_monkey:_, ref mut op, right_monkey:_ } = root.value else {
-            panic!("root isn't an expression!")
-        };
-        *op = Op::Equal;
-    }
-    let root = monkeys.monkeys.get(&root_id).unwrap();
-    calculate_human_value(&monkeys, root, my_id, 1)
+    solve_for(&monkeys, root_id, my_id).unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = try_parse(s);
 }
 
 #[cfg(test)]
@@ -266,14 +471,110 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 152);
 }
 
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 301);
 }
 
+#[cfg(test)]
+#[test]
+fn test_solver() {
+    let id = |s: &str| Id::from_str(s).unwrap();
+    let solve = |s: &str, unknown: &str| solve_for(&parse(s), id("root"), id(unknown));
+
+    let monkeys = parse(TEST_INPUT_STRING);
+    let side = |name| Expr::build(&monkeys, id(name), Some(id("humn")), &mut vec![], &mut HashMap::new());
+    assert_eq!(side("pppw").unwrap().to_string(), "((4 + (2 * (humn - 3))) / 4)");
+    assert_eq!(side("sjmn"), Ok(Rc::new(Expr::Constant(Rational::integer(150)))));
+
+    // Any monkey can be the unknown
+    assert_eq!(solve(TEST_INPUT_STRING, "sllz"), Ok(596));
+    assert_eq!(solve(TEST_INPUT_STRING, "dbpl"), Err(SolveError::NoIntegerSolution(Rational::new(1, 15).unwrap())));
+    // The unknown on both sides: 2x = x + 10
+    let both_sides = "root: left + rght\nleft: humn * twoo\nrght: humn + tenn\ntwoo: 2\ntenn: 10\nhumn: 0";
+    assert_eq!(solve(both_sides, "humn"), Ok(10));
+    // (x + x + x) / 2 = x + 3
+    let combination = "root: left + rght\nleft: sumx / twoo\nsumx: xtwo + humn\nxtwo: humn + humn\n\
+                       rght: humn + thre\ntwoo: 2\nthre: 3\nhumn: 0";
+    assert_eq!(solve(combination, "humn"), Ok(6));
+    // x = x + 1, x = x and x * x = 4
+    let unsolvable = "root: humn + rght\nrght: humn + onee\nonee: 1\nhumn: 0";
+    assert_eq!(solve(unsolvable, "humn"), Err(SolveError::NoSolution));
+    assert_eq!(solve("root: humn + humn\nhumn: 0", "humn"), Err(SolveError::InfinitelyManySolutions));
+    let square = "root: left + four\nleft: humn * humn\nfour: 4\nhumn: 0";
+    assert_eq!(solve(square, "humn"), Err(SolveError::NonLinear("(humn * humn)".to_owned())));
+    assert_eq!(solve("root: left + left\nleft: left + left", "humn"), Err(SolveError::Cycle(id("left"))));
+
+    // Each monkey doubles the one before, which would be 2^60 copies of the unknown if they weren't shared
+    let doubling = (1..=60)
+        .map(|n| format!("m{n:03}: m{:03} + m{:03}", n - 1, n - 1))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let doubling = format!("root: m060 + rght\nrght: {}\nm000: humn * onee\nonee: 1\nhumn: 0\n{doubling}", 3i64 << 60);
+    assert_eq!(solve(&doubling, "humn"), Ok(3));
+
+    // Too big to be exact, rather than quietly wrapping round
+    let huge =
+        "root: left + rght\nleft: humn * cube\ncube: squa * bigg\nsqua: bigg * bigg\nbigg: 1000000000000000000\n\
+                rght: 1\nhumn: 0";
+    assert_eq!(solve(huge, "humn"), Err(SolveError::Overflow));
+    let max = Rational::integer(i64::MAX);
+    assert_eq!(max.perform(Op::Mult, max), Ok(Rational { num: i64::MAX as i128 * i64::MAX as i128, den: 1 }));
+    let tiny = Rational::new(1, i128::MAX).unwrap();
+    assert_eq!(tiny.perform(Op::Div, Rational::integer(2)), Err(SolveError::Overflow));
+    assert_eq!(tiny.perform(Op::Plus, tiny), Ok(Rational::new(2, i128::MAX).unwrap()));
+}
+
+pub fn solve(input_string: &str) -> (i64, i64) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(i64, bool, i64, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
     Some((part1_answer, part1_answer == 291_425_799_367_130, part2_answer, part2_answer == 3_219_579_395_609))
 }
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn id() -> impl Strategy<Value = Id> {
+        "[a-z]{4}".prop_map(|s| Id::from_str(&s).unwrap())
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![Just(Op::Plus), Just(Op::Minus), Just(Op::Mult), Just(Op::Div), Just(Op::Equal)]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<i64>().prop_map(Value::Number),
+            (id(), op(), id()).prop_map(|(left_monkey, op, right_monkey)| Value::Operation {
+                left_monkey,
+                op,
+                right_monkey
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn id_round_trips(id in id()) {
+            prop_assert_eq!(Id::from_str(&id.to_string()), Ok(id));
+        }
+
+        #[test]
+        fn value_round_trips(v in value()) {
+            prop_assert_eq!(Value::from_str(&v.to_string()), Ok(v));
+        }
+
+        #[test]
+        fn parse_does_not_panic(s in "\\PC*") {
+            let _ = try_parse(&s);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::profile;
 use itertools::Itertools;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
@@ -130,12 +131,154 @@ impl Board {
         Coord { x, y }
     }
 
-    fn move_one_part2(&self, start: Coord, direction: Direction) -> (Coord, Direction) {
-        (Coord { x, y }, direction)
+    fn cell(&self, (x, y): (AbsX, Y)) -> Option<(Coord, Cell)> {
+        let row = self.rows.get(y.0)?;
+        let x = row.to_row_x(x)?;
+        Some((Coord { x, y }, row.cells[x.0]))
     }
 
-    fn move_part2(&self, start: Coord, direction: Direction, count: usize) -> (Coord, Direction) {
-        (0..count).fold((start, direction), |(pos, direction), _index| self.move_one_part2(pos, direction))
+    fn move_one_part2(&self, cube: &Cube, start: Coord, direction: Direction) -> (Coord, Direction) {
+        let (x, y) = self.absolute_location(start);
+        let (dx, dy) = direction.offset();
+        let next =
+            x.0.checked_add_signed(dx)
+                .zip(y.0.checked_add_signed(dy))
+                .and_then(|(x, y)| self.cell((AbsX(x), Y(y))));
+        let (next, next_direction) = match next {
+            Some(next) => (next, direction),
+            None => {
+                let (location, next_direction) = cube.wrap((x, y), direction);
+                (self.cell(location).expect("Cube faces are on the board"), next_direction)
+            }
+        };
+        match next {
+            (_, Cell::Wall) => (start, direction),
+            (location, Cell::Open) => (location, next_direction),
+        }
+    }
+
+    fn move_part2(&self, cube: &Cube, start: Coord, direction: Direction, count: usize) -> (Coord, Direction) {
+        (0..count).fold((start, direction), |(pos, direction), _index| self.move_one_part2(cube, pos, direction))
+    }
+}
+
+type Vector = [isize; 3];
+
+fn neg(v: Vector) -> Vector {
+    v.map(|c| -c)
+}
+
+fn dot(a: Vector, b: Vector) -> isize {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+// A face of the folded cube: where it sits on the board, and which way its outward normal and its board
+// right/down directions point in 3D.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Face {
+    origin: (AbsX, Y),
+    normal: Vector,
+    right: Vector,
+    down: Vector,
+}
+
+impl Face {
+    fn vector(&self, direction: Direction) -> Vector {
+        match direction {
+            Direction::Right => self.right,
+            Direction::Down => self.down,
+            Direction::Left => neg(self.right),
+            Direction::Up => neg(self.down),
+        }
+    }
+
+    // The face on the other side of the edge in `direction`, as if it were unfolded flat next to this one
+    fn fold(&self, direction: Direction, origin: (AbsX, Y)) -> Face {
+        let Face { normal, right, down, .. } = *self;
+        match direction {
+            Direction::Right => Face { origin, normal: right, right: neg(normal), down },
+            Direction::Down => Face { origin, normal: down, right, down: neg(normal) },
+            Direction::Left => Face { origin, normal: neg(right), right: normal, down },
+            Direction::Up => Face { origin, normal: neg(down), right, down: normal },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Cube {
+    size: usize,
+    faces: Vec<Face>,
+}
+
+impl Cube {
+    // Works out the face size from the area of the board, then walks the net from the first face, folding each
+    // newly reached face onto the cube relative to the face it was reached from.
+    fn fold(board: &Board) -> Result<Cube, String> {
+        let area = board.rows.iter().map(|row| row.cells.len()).sum::<usize>();
+        let size = (1..=area).find(|size| 6 * size * size >= area).unwrap_or(0);
+        if size == 0 || 6 * size * size != area {
+            return Err(format!("A board with {area} cells can't be folded into a cube"));
+        }
+        let is_face = |fx: usize, fy: usize| board.cell((AbsX(fx * size), Y(fy * size))).is_some();
+
+        let start = board.rows[0].offset / size;
+        let mut faces =
+            vec![Face { origin: (AbsX(start * size), Y(0)), normal: [0, 0, -1], right: [1, 0, 0], down: [0, 1, 0] }];
+        let mut to_visit = vec![(start, 0)];
+        while let Some((fx, fy)) = to_visit.pop() {
+            let face = *faces
+                .iter()
+                .find(|f| f.origin == (AbsX(fx * size), Y(fy * size)))
+                .unwrap();
+            for direction in [Direction::Right, Direction::Down, Direction::Left, Direction::Up] {
+                let (dx, dy) = direction.offset();
+                let Some((nx, ny)) = fx.checked_add_signed(dx).zip(fy.checked_add_signed(dy)) else { continue };
+                let origin = (AbsX(nx * size), Y(ny * size));
+                if is_face(nx, ny) && !faces.iter().any(|f| f.origin == origin) {
+                    faces.push(face.fold(direction, origin));
+                    to_visit.push((nx, ny));
+                }
+            }
+        }
+
+        if faces.len() != 6 || faces.iter().map(|f| f.normal).unique().count() != 6 {
+            return Err("The board isn't a cube net".to_owned());
+        }
+        Ok(Cube { size, faces })
+    }
+
+    fn face_containing(&self, (x, y): (AbsX, Y)) -> &Face {
+        self.faces
+            .iter()
+            .find(|f| {
+                (f.origin.0 .0..f.origin.0 .0 + self.size).contains(&x.0)
+                    && (f.origin.1 .0..f.origin.1 .0 + self.size).contains(&y.0)
+            })
+            .expect("Location isn't on a face")
+    }
+
+    // Steps off the edge of a face in `direction`, returning where that lands on the board and the direction of
+    // travel there. Works in 3D with doubled coordinates, so that cell centres on a face are odd offsets from the
+    // middle of the face and the face itself sits `size` from the centre of the cube.
+    fn wrap(&self, location: (AbsX, Y), direction: Direction) -> ((AbsX, Y), Direction) {
+        let size = self.size as isize;
+        let from = self.face_containing(location);
+        let u = (location.0 .0 - from.origin.0 .0) as isize;
+        let v = (location.1 .0 - from.origin.1 .0) as isize;
+        let heading = from.vector(direction);
+        let point: Vector = std::array::from_fn(|i| {
+            size * from.normal[i] + (2 * u + 1 - size) * from.right[i] + (2 * v + 1 - size) * from.down[i] + heading[i]
+                - from.normal[i]
+        });
+
+        let to = self.faces.iter().find(|f| f.normal == heading).unwrap();
+        let u = (dot(point, to.right) + size - 1) / 2;
+        let v = (dot(point, to.down) + size - 1) / 2;
+        let direction = [Direction::Right, Direction::Down, Direction::Left, Direction::Up]
+            .into_iter()
+            .find(|d| to.vector(*d) == neg(from.normal))
+            .unwrap();
+        ((AbsX(to.origin.0 .0 + u as usize), Y(to.origin.1 .0 + v as usize)), direction)
     }
 }
 
@@ -148,6 +291,14 @@ enum Direction {
 }
 
 impl Direction {
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Right => (1, 0),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Up => (0, -1),
+        }
+    }
     fn turn_left(self) -> Self {
         match self {
             Direction::Right => Direction::Up,
@@ -200,10 +351,10 @@ impl Player {
             Move::TurnRight => Player { direction: self.direction.turn_right(), ..*self },
         }
     }
-    fn perform_movement_part2(&self, board: &Board, movement: Move) -> Self {
+    fn perform_movement_part2(&self, board: &Board, cube: &Cube, movement: Move) -> Self {
         match movement {
             Move::Forward(count) => {
-                let (new_location, new_direction) = board.move_part2(self.location, self.direction, count);
+                let (new_location, new_direction) = board.move_part2(cube, self.location, self.direction, count);
                 Player { location: new_location, direction: new_direction }
             }
             Move::TurnLeft => Player { direction: self.direction.turn_left(), ..*self },
@@ -270,7 +421,21 @@ fn part1_evaluate(s: &str) -> usize {
 }
 
 fn part2_evaluate(s: &str) -> usize {
-    0
+    let Ok((board, moves)) = parse(s) else {
+        panic!("Bad input!");
+    };
+    let cube = Cube::fold(&board).unwrap_or_else(|e| panic!("{e}"));
+    let mut p = Player::new();
+    for movement in moves {
+        p = p.perform_movement_part2(&board, &cube, movement);
+    }
+    let abs_loc = board.absolute_location(p.location);
+    (1000 * (abs_loc.1 .0 + 1)) + (4 * (abs_loc.0 .0 + 1)) + (p.direction as usize)
+}
+
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_parse(s: &str) {
+    let _ = parse(s);
 }
 
 #[cfg(test)]
@@ -338,14 +503,168 @@ fn test_part1() {
     assert_eq!(part1_evaluate(TEST_INPUT_STRING), 6032);
 }
 
+#[cfg(test)]
+#[test]
+fn test_cube_wrapping() {
+    let (board, _) = parse(TEST_INPUT_STRING).unwrap();
+    let cube = Cube::fold(&board).unwrap();
+    assert_eq!(cube.size, 4);
+
+    // The examples from the puzzle text
+    assert_eq!(board.move_one_part2(&cube, coord(11, 5), Direction::Right), (coord(6, 8), Direction::Down));
+    assert_eq!(board.move_one_part2(&cube, coord(2, 11), Direction::Down), (coord(1, 7), Direction::Up));
+    // Blocked by a wall on the other side of the edge
+    assert_eq!(board.move_one_part2(&cube, coord(0, 4), Direction::Up), (coord(0, 4), Direction::Up));
+    assert_eq!(board.move_one_part2(&cube, coord(1, 4), Direction::Up), (coord(2, 0), Direction::Down));
+}
+
+// Walking 4 faces in a straight line on an empty cube gets back to where you started, whatever the net.
+#[cfg(test)]
+#[test]
+fn test_cube_nets() {
+    let nets = [
+        "  ..\n  ..\n........\n........\n  ..\n  ..",
+        "....\n....\n  ....\n  ....\n    ....\n    ....",
+        "......\n......\n    ......\n    ......",
+        "..\n..\n......\n......\n    ....\n    ....",
+        TEST_INPUT_STRING.split_once("\n\n").unwrap().0,
+    ];
+    // The rest of the eleven, drawn a face to a character
+    let faces = ["#\n####\n#", "#\n####\n.#", "#\n####\n..#", "#\n####\n...#", ".#\n####\n..#", ".#\n###\n..##"];
+    let expanded = faces.map(|layout| {
+        layout
+            .lines()
+            .map(|row| {
+                row.chars()
+                    .map(|face| if face == '#' { ".." } else { "  " })
+                    .collect::<String>()
+            })
+            .flat_map(|row| [row.clone(), row])
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    for net in nets.into_iter().chain(expanded.iter().map(String::as_str)) {
+        let (board, _) = parse(&format!("{}\n\n1", net.replace('#', "."))).unwrap();
+        let cube = Cube::fold(&board).unwrap_or_else(|e| panic!("{e}:\n{net}"));
+        for (y, row) in board.rows.iter().enumerate() {
+            for x in 0..row.cells.len() {
+                for direction in [Direction::Right, Direction::Down, Direction::Left, Direction::Up] {
+                    let start = coord(x, y);
+                    let end = board.move_part2(&cube, start, direction, 4 * cube.size);
+                    assert_eq!(end, (start, direction), "{net}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
 #[test]
 fn test_part2() {
     assert_eq!(part2_evaluate(TEST_INPUT_STRING), 5031);
 }
 
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
 pub fn run() -> Option<(usize, bool, usize, bool)> {
-    let input_string = include_str!("./input.txt");
-    let part1_answer = part1_evaluate(input_string);
-    let part2_answer = part2_evaluate(input_string);
-    Some((part1_answer, part1_answer == 31568, part2_answer, part2_answer == 0))
+    let (part1_answer, part2_answer) = solve(include_str!("./input.txt"));
+    Some((part1_answer, part1_answer == 31568, part2_answer, part2_answer == 36540))
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn render(board: &Board, moves: &[Move]) -> String {
+        let board = board
+            .rows
+            .iter()
+            .map(|row| {
+                let cells = row
+                    .cells
+                    .iter()
+                    .map(|c| if *c == Cell::Wall { '#' } else { '.' });
+                " ".repeat(row.offset)
+                    .chars()
+                    .chain(cells)
+                    .collect::<String>()
+            })
+            .join("\n");
+        let moves = moves
+            .iter()
+            .map(|m| match m {
+                Move::Forward(n) => n.to_string(),
+                Move::TurnRight => "R".to_owned(),
+                Move::TurnLeft => "L".to_owned(),
+            })
+            .collect::<String>();
+        format!("{board}\n\n{moves}")
+    }
+
+    fn open(board: &Board, location: Coord) -> bool {
+        board.rows[location.y.0].cells.get(location.x.0) == Some(&Cell::Open)
+    }
+
+    fn board() -> impl Strategy<Value = Board> {
+        prop::collection::vec((0..4usize, prop::collection::vec(any::<bool>(), 1..8)), 1..8).prop_map(|rows| {
+            let rows = rows.into_iter().enumerate().map(|(y, (offset, walls))| {
+                let cells = walls
+                    .into_iter()
+                    .enumerate()
+                    // The walk starts on the first cell of the top row
+                    .map(|(x, wall)| if wall && (x, y) != (0, 0) { Cell::Wall } else { Cell::Open })
+                    .collect();
+                Row { offset, cells }
+            });
+            Board { rows: rows.collect() }
+        })
+    }
+
+    // Turns go between the numbers, since two numbers in a row would read back as one
+    fn moves() -> impl Strategy<Value = Moves> {
+        prop::collection::vec((0..20usize, any::<bool>()), 0..20).prop_map(|steps| {
+            steps
+                .into_iter()
+                .flat_map(|(n, right)| [Move::Forward(n), if right { Move::TurnRight } else { Move::TurnLeft }])
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn parse_does_not_panic(s in "[ .#\n]*(\n\n)?[0-9LR]*") {
+            let _ = parse(&s);
+        }
+
+        #[test]
+        fn parse_round_trips(board in board(), moves in moves()) {
+            prop_assert_eq!(parse(&render(&board, &moves)), Ok((board, moves)));
+        }
+
+        #[test]
+        fn part1_walk_stays_on_open_cells(board in board(), moves in moves()) {
+            let (board, moves) = parse(&render(&board, &moves)).unwrap();
+            let mut p = Player::new();
+            for movement in moves {
+                p = p.perform_movement_part1(&board, movement);
+                prop_assert!(open(&board, p.location), "{:?} is not an open cell", p.location);
+            }
+        }
+
+        #[test]
+        fn part2_walk_stays_on_open_cells(moves in moves()) {
+            let (board, _) = parse(TEST_INPUT_STRING).unwrap();
+            let cube = Cube::fold(&board).unwrap();
+            let mut p = Player::new();
+            for movement in moves {
+                p = p.perform_movement_part2(&board, &cube, movement);
+                prop_assert!(open(&board, p.location), "{:?} is not an open cell", p.location);
+            }
+        }
+    }
 }
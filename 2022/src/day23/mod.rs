@@ -0,0 +1,175 @@
+use super::profile;
+use std::collections::{HashMap, HashSet};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+struct Coord {
+    x: i32,
+    y: i32,
+}
+
+impl Coord {
+    fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+    fn offset(self, (dx, dy): (i32, i32)) -> Self {
+        Self::new(self.x + dx, self.y + dy)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+enum Direction {
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    // The step in this direction, followed by the three neighbours that must be empty to propose it
+    fn neighbours(self) -> [(i32, i32); 3] {
+        match self {
+            Direction::North => [(0, -1), (-1, -1), (1, -1)],
+            Direction::South => [(0, 1), (-1, 1), (1, 1)],
+            Direction::West => [(-1, 0), (-1, -1), (-1, 1)],
+            Direction::East => [(1, 0), (1, -1), (1, 1)],
+        }
+    }
+}
+
+const ALL_NEIGHBOURS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+// Only the occupied tiles are stored, so the elves can spread as far as they like
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Grove {
+    elves: HashSet<Coord>,
+    directions: [Direction; 4],
+}
+
+impl Grove {
+    fn parse(s: &str) -> Self {
+        let elves = s
+            .lines()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c == '#')
+                    .map(move |(x, _)| Coord::new(x as i32, y as i32))
+            })
+            .collect();
+        Grove { elves, directions: [Direction::North, Direction::South, Direction::West, Direction::East] }
+    }
+
+    fn is_empty(&self, c: Coord) -> bool {
+        !self.elves.contains(&c)
+    }
+
+    fn proposal(&self, elf: Coord) -> Option<Coord> {
+        if ALL_NEIGHBOURS
+            .iter()
+            .all(|offset| self.is_empty(elf.offset(*offset)))
+        {
+            return None;
+        }
+        self.directions.iter().find_map(|direction| {
+            let neighbours = direction.neighbours();
+            neighbours
+                .iter()
+                .all(|offset| self.is_empty(elf.offset(*offset)))
+                .then(|| elf.offset(neighbours[0]))
+        })
+    }
+
+    // Runs one round, returning whether any elf moved
+    fn round(&mut self) -> bool {
+        let mut proposals = HashMap::<Coord, Vec<Coord>>::new();
+        for elf in &self.elves {
+            if let Some(target) = self.proposal(*elf) {
+                proposals.entry(target).or_default().push(*elf);
+            }
+        }
+        // Elves proposing the same tile all stay put
+        let mut moved = false;
+        for (target, elves) in proposals {
+            if let [elf] = elves[..] {
+                self.elves.remove(&elf);
+                self.elves.insert(target);
+                moved = true;
+            }
+        }
+        self.directions.rotate_left(1);
+        moved
+    }
+
+    fn empty_ground(&self) -> usize {
+        if self.elves.is_empty() {
+            return 0;
+        }
+        let (x_min, x_max, y_min, y_max) =
+            self.elves
+                .iter()
+                .fold((i32::MAX, i32::MIN, i32::MAX, i32::MIN), |(x_min, x_max, y_min, y_max), elf| {
+                    (x_min.min(elf.x), x_max.max(elf.x), y_min.min(elf.y), y_max.max(elf.y))
+                });
+        ((x_max - x_min + 1) * (y_max - y_min + 1)) as usize - self.elves.len()
+    }
+}
+
+fn part1_evaluate(s: &str) -> usize {
+    let mut grove = Grove::parse(s);
+    for _ in 0..10 {
+        grove.round();
+    }
+    grove.empty_ground()
+}
+
+fn part2_evaluate(s: &str) -> usize {
+    let mut grove = Grove::parse(s);
+    (1..).find(|_| !grove.round()).unwrap()
+}
+
+#[cfg(test)]
+const TEST_INPUT_STRING: &str = "\
+....#..
+..###.#
+#...#.#
+.#...##
+#.###..
+##.#.##
+.#..#..";
+
+#[cfg(test)]
+#[test]
+fn test_small_example() {
+    let mut grove = Grove::parse(".....\n..##.\n..#..\n.....\n..##.\n.....");
+    let after = |s: &str| Grove::parse(s).elves;
+    grove.round();
+    assert_eq!(grove.elves, after("..##.\n.....\n..#..\n...#.\n..#..\n....."));
+    grove.round();
+    assert_eq!(grove.elves, after(".....\n..##.\n.#...\n....#\n.....\n..#.."));
+    grove.round();
+    assert_eq!(grove.elves, after("..#..\n....#\n#....\n....#\n.....\n..#.."));
+    assert!(!grove.round());
+}
+
+#[cfg(test)]
+#[test]
+fn test_part1() {
+    assert_eq!(part1_evaluate(TEST_INPUT_STRING), 110);
+}
+
+#[cfg(test)]
+#[test]
+fn test_part2() {
+    assert_eq!(part2_evaluate(TEST_INPUT_STRING), 20);
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
+pub fn run() -> Option<(usize, bool, usize, bool)> {
+    super::downloaded::run(23, solve)
+}
@@ -0,0 +1,225 @@
+use super::profile;
+use std::collections::HashSet;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+struct Coord {
+    x: i32,
+    y: i32,
+}
+
+impl Coord {
+    fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+// The valley is the area inside the walls, with (0, 0) in its top left corner. The entrance is the gap in the top
+// wall and the exit the gap in the bottom one.
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Valley {
+    width: i32,
+    height: i32,
+    // The blizzards where they start, as they'd be drawn at time 0
+    cells: Vec<Vec<char>>,
+    entrance: Coord,
+    exit: Coord,
+}
+
+impl Valley {
+    fn parse(s: &str) -> Result<Self, String> {
+        let lines = s
+            .lines()
+            .map(|line| line.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let (Some(top), Some(bottom)) = (lines.first(), lines.last()) else { return Err("No valley".to_owned()) };
+        if lines.len() < 3 || top.len() < 3 {
+            return Err("The valley needs walls all round it and room inside".to_owned());
+        }
+        if let Some(row) = lines.iter().position(|line| line.len() != top.len()) {
+            return Err(format!("Row {row} isn't as wide as the top wall"));
+        }
+        // Walls all round, except for one gap in the top and bottom
+        let gap = |wall: &[char], y: i32| {
+            let gaps = wall
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c != '#')
+                .collect::<Vec<_>>();
+            match gaps[..] {
+                [(x, '.')] if x > 0 && x < wall.len() - 1 => Ok(Coord::new(x as i32 - 1, y)),
+                _ => Err(format!("Row {} should be a wall with one gap in it", y + 1)),
+            }
+        };
+        let height = lines.len() as i32 - 2;
+        let (entrance, exit) = (gap(top, -1)?, gap(bottom, height)?);
+        let mut cells = Vec::new();
+        for (row, line) in lines[1..lines.len() - 1].iter().enumerate() {
+            if line[0] != '#' || line[line.len() - 1] != '#' {
+                return Err(format!("Row {} isn't walled in", row + 1));
+            }
+            let inside = line[1..line.len() - 1].to_vec();
+            if let Some(c) = inside
+                .iter()
+                .find(|c| !matches!(c, '.' | '>' | '<' | '^' | 'v'))
+            {
+                return Err(format!("Bad character {c} in row {}", row + 1));
+            }
+            cells.push(inside);
+        }
+        Ok(Valley { width: top.len() as i32 - 2, height, cells, entrance, exit })
+    }
+
+    fn entrance(&self) -> Coord {
+        self.entrance
+    }
+
+    fn exit(&self) -> Coord {
+        self.exit
+    }
+
+    // Blizzards wrap around, so the whole valley repeats with this period
+    fn period(&self) -> usize {
+        lcm(self.width as usize, self.height as usize)
+    }
+
+    fn initial(&self, x: i32, y: i32) -> char {
+        self.cells[y.rem_euclid(self.height) as usize][x.rem_euclid(self.width) as usize]
+    }
+
+    // Rather than moving every blizzard each minute, look back along each row and column to where a blizzard would
+    // have had to start to be here now.
+    fn is_clear(&self, c: Coord, time: usize) -> bool {
+        if c == self.entrance() || c == self.exit() {
+            return true;
+        }
+        if c.x < 0 || c.x >= self.width || c.y < 0 || c.y >= self.height {
+            return false;
+        }
+        let t = (time % self.period()) as i32;
+        self.initial(c.x - t, c.y) != '>'
+            && self.initial(c.x + t, c.y) != '<'
+            && self.initial(c.x, c.y - t) != 'v'
+            && self.initial(c.x, c.y + t) != '^'
+    }
+
+    // Breadth first search through (position, time) space: every position reachable at the next minute comes from
+    // a position reachable at this one. States are only revisited modulo the period, so an unreachable goal is
+    // detected once the frontier dies out.
+    fn earliest_arrival(&self, from: Coord, to: Coord, start_time: usize) -> Option<usize> {
+        let period = self.period();
+        let mut seen = HashSet::from([(from, start_time % period)]);
+        let mut frontier = vec![from];
+        let mut time = start_time;
+        while !frontier.is_empty() {
+            time += 1;
+            let mut next = Vec::new();
+            for c in frontier {
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (-1, 0), (0, -1)] {
+                    let n = Coord::new(c.x + dx, c.y + dy);
+                    if n == to {
+                        return Some(time);
+                    }
+                    if self.is_clear(n, time) && seen.insert((n, time % period)) {
+                        next.push(n);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        None
+    }
+}
+
+fn parse(s: &str) -> Valley {
+    Valley::parse(s).unwrap_or_else(|e| panic!("{e}"))
+}
+
+fn part1_evaluate(s: &str) -> usize {
+    let valley = parse(s);
+    valley
+        .earliest_arrival(valley.entrance(), valley.exit(), 0)
+        .expect("No way through the valley")
+}
+
+fn part2_evaluate(s: &str) -> usize {
+    let valley = parse(s);
+    let (entrance, exit) = (valley.entrance(), valley.exit());
+    [(entrance, exit), (exit, entrance), (entrance, exit)]
+        .into_iter()
+        .try_fold(0, |time, (from, to)| valley.earliest_arrival(from, to, time))
+        .expect("No way through the valley")
+}
+
+#[cfg(test)]
+const TEST_INPUT_STRING: &str = "\
+#.######
+#>>.<^<#
+#.<..<<#
+#>v.><>#
+#<^v^^>#
+######.#";
+
+#[cfg(test)]
+#[test]
+fn test_blizzards() {
+    let valley = parse("#.#####\n#.....#\n#>....#\n#.....#\n#...v.#\n#.....#\n#####.#");
+    assert_eq!(valley.period(), 5);
+    assert!(!valley.is_clear(Coord::new(0, 1), 0));
+    assert!(!valley.is_clear(Coord::new(3, 1), 3));
+    assert!(!valley.is_clear(Coord::new(0, 1), 5));
+    assert!(valley.is_clear(Coord::new(4, 1), 5));
+    assert!(!valley.is_clear(Coord::new(3, 4), 1));
+    assert!(!valley.is_clear(Coord::new(3, 0), 2));
+}
+
+#[cfg(test)]
+#[test]
+fn test_valley() {
+    // The gaps can be anywhere along the walls
+    let valley = parse("###.#\n#...#\n#.###");
+    assert_eq!((valley.entrance(), valley.exit()), (Coord::new(2, -1), Coord::new(0, 1)));
+    assert_eq!(part1_evaluate("###.#\n#...#\n#.###"), 4);
+
+    assert_eq!(Valley::parse(""), Err("No valley".to_owned()));
+    assert!(Valley::parse("#.#\n#.#").is_err());
+    assert_eq!(Valley::parse("#####\n#...#\n#.###"), Err("Row 0 should be a wall with one gap in it".to_owned()));
+    assert_eq!(Valley::parse("#..##\n#...#\n#.###"), Err("Row 0 should be a wall with one gap in it".to_owned()));
+    assert_eq!(Valley::parse(".####\n#...#\n#.###"), Err("Row 0 should be a wall with one gap in it".to_owned()));
+    assert_eq!(Valley::parse("#.###\n#..#\n#.###"), Err("Row 1 isn't as wide as the top wall".to_owned()));
+    assert_eq!(Valley::parse("#.###\n#...#\n#....\n##.##"), Err("Row 2 isn't walled in".to_owned()));
+    assert_eq!(Valley::parse("#.###\n#.x.#\n##.##"), Err("Bad character x in row 1".to_owned()));
+}
+
+#[cfg(test)]
+#[test]
+fn test_part1() {
+    assert_eq!(part1_evaluate(TEST_INPUT_STRING), 18);
+}
+
+#[cfg(test)]
+#[test]
+fn test_part2() {
+    assert_eq!(part2_evaluate(TEST_INPUT_STRING), 54);
+}
+
+pub fn solve(input_string: &str) -> (usize, usize) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    let part2_answer = profile::part("part 2", || part2_evaluate(input_string));
+    (part1_answer, part2_answer)
+}
+
+pub fn run() -> Option<(usize, bool, usize, bool)> {
+    super::downloaded::run(24, solve)
+}
@@ -0,0 +1,264 @@
+use super::profile;
+use std::fmt::Display;
+use std::iter::Sum;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// A number written in balanced base 5, with digits `=` (-2), `-` (-1), `0`, `1` and `2`.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Snafu(i128);
+
+fn digit_value(c: char) -> Result<i128, String> {
+    match c {
+        '=' => Ok(-2),
+        '-' => Ok(-1),
+        '0' => Ok(0),
+        '1' => Ok(1),
+        '2' => Ok(2),
+        c => Err(format!("Bad SNAFU digit {c}")),
+    }
+}
+
+impl FromStr for Snafu {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("Empty SNAFU number".to_owned());
+        }
+        let mut value = 0i128;
+        for c in s.chars() {
+            let digit = digit_value(c)?;
+            value = value
+                .checked_mul(5)
+                .and_then(|value| value.checked_add(digit))
+                .ok_or_else(|| format!("SNAFU number {s} is too big"))?;
+        }
+        Ok(Snafu(value))
+    }
+}
+
+impl Display for Snafu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0");
+        }
+        // Each digit is the remainder mod 5, shifted into -2..=2 by carrying into the next digit. This works the same
+        // for negative numbers, and is arranged so that nothing overflows at the extremes of i128.
+        let mut digits = Vec::new();
+        let mut n = self.0;
+        while n != 0 {
+            let remainder = n.rem_euclid(5);
+            let digit = if remainder > 2 { remainder - 5 } else { remainder };
+            digits.push(match digit {
+                -2 => '=',
+                -1 => '-',
+                0 => '0',
+                1 => '1',
+                _ => '2',
+            });
+            n = n.div_euclid(5) + i128::from(remainder > 2);
+        }
+        write!(f, "{}", digits.iter().rev().collect::<String>())
+    }
+}
+
+impl From<i64> for Snafu {
+    fn from(n: i64) -> Self {
+        Snafu(n as i128)
+    }
+}
+
+impl From<i128> for Snafu {
+    fn from(n: i128) -> Self {
+        Snafu(n)
+    }
+}
+
+impl From<Snafu> for i128 {
+    fn from(n: Snafu) -> Self {
+        n.0
+    }
+}
+
+impl TryFrom<Snafu> for i64 {
+    type Error = String;
+
+    fn try_from(n: Snafu) -> Result<Self, Self::Error> {
+        i64::try_from(n.0).map_err(|_| format!("SNAFU number {n} doesn't fit in an i64"))
+    }
+}
+
+// Like the integer types, but checked in release builds too: the operators panic rather than wrap
+impl Snafu {
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Snafu)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Snafu)
+    }
+
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        self.0.checked_mul(rhs.0).map(Snafu)
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        self.0.checked_neg().map(Snafu)
+    }
+}
+
+impl Add for Snafu {
+    type Output = Snafu;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs)
+            .unwrap_or_else(|| panic!("SNAFU overflow in {self} + {rhs}"))
+    }
+}
+
+impl Sub for Snafu {
+    type Output = Snafu;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs)
+            .unwrap_or_else(|| panic!("SNAFU overflow in {self} - {rhs}"))
+    }
+}
+
+impl Mul for Snafu {
+    type Output = Snafu;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.checked_mul(rhs)
+            .unwrap_or_else(|| panic!("SNAFU overflow in {self} * {rhs}"))
+    }
+}
+
+impl Neg for Snafu {
+    type Output = Snafu;
+
+    fn neg(self) -> Self::Output {
+        self.checked_neg()
+            .unwrap_or_else(|| panic!("SNAFU overflow in -{self}"))
+    }
+}
+
+impl Sum for Snafu {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Snafu::default(), Add::add)
+    }
+}
+
+fn part1_evaluate(s: &str) -> Snafu {
+    s.lines()
+        .map(|line| Snafu::from_str(line).unwrap_or_else(|e| panic!("{e}")))
+        .sum()
+}
+
+// The last day only has the one puzzle
+const PART2_ANSWER: &str = "Start the blender!";
+
+#[cfg(test)]
+const TEST_INPUT_STRING: &str = "\
+1=-0-2
+12111
+2=0=
+21
+2=01
+111
+20012
+112
+1=-1=
+1-12
+12
+1=
+122";
+
+#[cfg(test)]
+#[test]
+fn test_conversions() {
+    let table = [
+        (1, "1"),
+        (2, "2"),
+        (3, "1="),
+        (4, "1-"),
+        (5, "10"),
+        (6, "11"),
+        (7, "12"),
+        (8, "2="),
+        (9, "2-"),
+        (10, "20"),
+        (15, "1=0"),
+        (20, "1-0"),
+        (2022, "1=11-2"),
+        (12345, "1-0---0"),
+        (314159265, "1121-1110-1=0"),
+    ];
+    for (decimal, snafu) in table {
+        assert_eq!(Snafu::from(decimal as i64).to_string(), snafu);
+        assert_eq!(snafu.parse::<Snafu>().map(i128::from), Ok(decimal));
+    }
+    assert_eq!(Snafu::from(0i64).to_string(), "0");
+    assert_eq!(Snafu::from(-3i64).to_string(), "-2");
+    assert!("".parse::<Snafu>().is_err());
+    assert!("1=3".parse::<Snafu>().is_err());
+    assert!("2".repeat(60).parse::<Snafu>().is_err());
+    assert!(i64::try_from(Snafu::from(i128::MAX)).is_err());
+    for extreme in [i128::MAX, i128::MIN + 1] {
+        assert_eq!(Snafu::from(extreme).to_string().parse(), Ok(Snafu::from(extreme)));
+    }
+
+    let (max, min) = (Snafu::from(i128::MAX), Snafu::from(i128::MIN));
+    assert_eq!(max.checked_add(Snafu::from(1i64)), None);
+    assert_eq!(min.checked_sub(Snafu::from(1i64)), None);
+    assert_eq!(max.checked_mul(Snafu::from(2i64)), None);
+    assert_eq!(min.checked_neg(), None);
+    assert_eq!(-max, Snafu::from(-i128::MAX));
+    assert_eq!(Snafu::from(-7i64) * Snafu::from(3i64) + Snafu::from(1i64), Snafu::from(-20i64));
+    assert!(std::panic::catch_unwind(|| max + max).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_part1() {
+    assert_eq!(part1_evaluate(TEST_INPUT_STRING).to_string(), "2=-1=0");
+}
+
+pub fn solve(input_string: &str) -> (Snafu, &'static str) {
+    let part1_answer = profile::part("part 1", || part1_evaluate(input_string));
+    (part1_answer, PART2_ANSWER)
+}
+
+pub fn run() -> Option<(Snafu, bool, &'static str, bool)> {
+    super::downloaded::run(25, solve)
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn i64_round_trips(n in any::<i64>()) {
+            let snafu = Snafu::from(n);
+            prop_assert_eq!(snafu.to_string().parse::<Snafu>(), Ok(snafu));
+            prop_assert_eq!(i64::try_from(snafu), Ok(n));
+        }
+
+        #[test]
+        fn strings_round_trip(s in "[12][=012-]{0,20}") {
+            prop_assert_eq!(s.parse::<Snafu>().map(|n| n.to_string()), Ok(s));
+        }
+
+        #[test]
+        fn arithmetic_matches_integers(a in any::<i32>(), b in any::<i32>()) {
+            let (sa, sb) = (Snafu::from(a as i64), Snafu::from(b as i64));
+            prop_assert_eq!(i128::from(sa + sb), a as i128 + b as i128);
+            prop_assert_eq!(i128::from(sa - sb), a as i128 - b as i128);
+            prop_assert_eq!(i128::from(sa * sb), a as i128 * b as i128);
+            prop_assert_eq!(i128::from(-sa), -(a as i128));
+        }
+    }
+}
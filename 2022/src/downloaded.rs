@@ -0,0 +1,92 @@
+//! Days whose puzzle input isn't checked in.
+//!
+//! Days 1 to 22 bundle their input with `include_str!` and hard-code the accepted answers. Days 23 to 25 read theirs
+//! at runtime instead, from `src/dayNN/input.txt` in the crate directory the runner was built from, which is where
+//! `--download <day>` saves it, so they build before the input has been fetched. Their accepted answers are kept
+//! beside the input in `input.answers`, in the same format as batch mode's, and `--submit` fills them in as the
+//! server accepts them. Until a part's answer has been accepted it doesn't count as right.
+
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use crate::batch::{read_expected_answers, PartResult, Verdict};
+
+pub const DAYS: RangeInclusive<usize> = 23..=25;
+
+/// Where `--download` saves the day's input, and where the day's `run()` looks for it
+pub fn input_path(day_number: usize) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("src/day{day_number:02}/input.txt"))
+}
+
+/// Runs the day against its downloaded input, or gives `None` if it hasn't been downloaded yet
+pub fn run<T: Display, U: Display>(day_number: usize, solve: fn(&str) -> (T, U)) -> Option<(T, bool, U, bool)> {
+    let path = input_path(day_number);
+    if !path.exists() {
+        eprintln!("Day {day_number}'s input isn't checked in, fetch it with --download {day_number}");
+        return None;
+    }
+    match run_from(&path, solve) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            eprintln!("Couldn't read day {day_number}'s input or answers from {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+fn run_from<T: Display, U: Display>(path: &Path, solve: fn(&str) -> (T, U)) -> io::Result<(T, bool, U, bool)> {
+    let input = fs::read_to_string(path)?;
+    let [expected1, expected2] = read_expected_answers(path)?;
+    let (part1, part2) = solve(&input);
+    let good = |answer: String, expected: Option<String>| {
+        PartResult::judge(answer, expected.as_deref()).verdict == Verdict::Correct
+    };
+    let (part1_good, part2_good) = (good(part1.to_string(), expected1), good(part2.to_string(), expected2));
+    Ok((part1, part1_good, part2, part2_good))
+}
+
+/// Keeps an answer the server accepted, so that later runs check it
+pub fn record_answer(day_number: usize, part: usize, answer: &str) -> io::Result<()> {
+    record_answer_for(&input_path(day_number), part, answer)
+}
+
+fn record_answer_for(input_path: &Path, part: usize, answer: &str) -> io::Result<()> {
+    let mut answers = read_expected_answers(input_path)?;
+    let slot = answers
+        .get_mut(part.wrapping_sub(1))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("there's no part {part}")))?;
+    *slot = Some(answer.to_owned());
+    let [part1, part2] = answers.map(Option::unwrap_or_default);
+    fs::write(input_path.with_extension("answers"), format!("{}\n{}\n", part1.trim_end(), part2.trim_end()))
+}
+
+#[cfg(test)]
+#[test]
+fn test_downloaded() {
+    let dir = std::env::temp_dir().join(format!("aoc2022-downloaded-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("input.txt");
+    let solve: fn(&str) -> (usize, String) = |s| (s.len(), s.to_uppercase());
+
+    let missing = run_from(&path, solve).map_err(|e| e.kind());
+    fs::write(&path, "abc").unwrap();
+    let unanswered = run_from(&path, solve).unwrap();
+    record_answer_for(&path, 2, "ABC").unwrap();
+    let part2_answered = run_from(&path, solve).unwrap();
+    record_answer_for(&path, 1, "3").unwrap();
+    let both_answered = run_from(&path, solve).unwrap();
+    let answers = fs::read_to_string(path.with_extension("answers")).unwrap();
+    let bad_part = record_answer_for(&path, 3, "x").map_err(|e| e.kind());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(missing, Err(io::ErrorKind::NotFound));
+    assert_eq!(unanswered, (3, false, "ABC".to_owned(), false));
+    assert_eq!(part2_answered, (3, false, "ABC".to_owned(), true));
+    assert_eq!(both_answered, (3, true, "ABC".to_owned(), true));
+    assert_eq!(answers, "3\nABC\n");
+    assert_eq!(bad_part, Err(io::ErrorKind::InvalidInput));
+    assert!(input_path(23).ends_with("src/day23/input.txt"));
+}
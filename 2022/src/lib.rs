@@ -1,4 +1,8 @@
+pub mod batch;
+pub mod client;
+pub mod downloaded;
 mod grid;
+pub mod profile;
 
 pub mod day01;
 pub mod day02;
@@ -22,6 +26,6 @@ pub mod day19;
 pub mod day20;
 pub mod day21;
 pub mod day22;
-// pub mod day23;
-// pub mod day24;
-// pub mod day25;
+pub mod day23;
+pub mod day24;
+pub mod day25;
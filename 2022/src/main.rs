@@ -1,17 +1,26 @@
 use std::env;
 use std::fmt::Display;
+use std::path::Path;
 
 use aoc2022::*;
+use tracing_subscriber::EnvFilter;
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOCATOR: profile::CountingAllocator = profile::CountingAllocator;
 
 fn time<T: Display, U: Display>(day_number: usize, f: &dyn Fn() -> Option<(T, bool, U, bool)>) -> u128 {
     let now = std::time::Instant::now();
-    let output = f();
+    let (output, allocations) = profile::profile(f);
     let duration = now.elapsed().as_micros();
     if let Some((part1_answer, part1_good, part2_answer, part2_good)) = output {
         println!("Day {day_number}, part 1 = {part1_answer} [{part1_good}], part 2 = {part2_answer} [{part2_good}] - took {duration} microseconds");
+        if let Some(allocations) = allocations {
+            println!("{allocations}");
+        }
         duration
     } else {
-        println!("Day {day_number} not yet implemented");
+        println!("Day {day_number} not yet implemented, or its input hasn't been downloaded");
         0
     }
 }
@@ -41,15 +50,112 @@ fn dispatch_day(day_number: usize) -> u128 {
         20 => time(day_number, &day20::run),
         21 => time(day_number, &day21::run),
         22 => time(day_number, &day22::run),
-        // 23 => time(day_number, &day23::run),
-        // 24 => time(day_number, &day24::run),
-        // 25 => time(day_number, &day25::run),
+        23 => time(day_number, &day23::run),
+        24 => time(day_number, &day24::run),
+        25 => time(day_number, &day25::run),
         _ => time::<bool, bool>(day_number, &oob),
     }
 }
 
+// Solver spans and events are only collected when a subscriber is installed, i.e. when `--explain` is passed or
+// `RUST_LOG` is set. `RUST_LOG` takes precedence over the `--explain` default of `debug`.
+fn init_tracing(explain: bool) {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) if explain => EnvFilter::new("debug"),
+        Err(_) => return,
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+fn talk_to_server(submit: bool, force: bool, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let client = client::Client::from_env()?;
+    let day_number = args.first().ok_or("missing day")?.parse::<usize>()?;
+    if submit {
+        let part = args.get(1).ok_or("missing part")?.parse::<usize>()?;
+        let answer = args.get(2).ok_or("missing answer")?;
+        let verdict = client.submit(batch::YEAR, day_number, part, answer)?;
+        println!("Day {day_number}, part {part} = {answer}: {verdict}");
+        // Days that read their input at runtime check their answers against the ones accepted so far
+        if verdict == client::Verdict::Correct && downloaded::DAYS.contains(&day_number) {
+            downloaded::record_answer(day_number, part, answer)?;
+        }
+    } else {
+        let path = downloaded::input_path(day_number);
+        if path.exists() && !force {
+            return Err(format!("{} already exists, pass --force to replace it", path.display()).into());
+        }
+        let input = client.input(batch::YEAR, day_number)?;
+        std::fs::write(&path, input)?;
+        println!("Saved day {day_number} input to {}", path.display());
+    }
+    Ok(())
+}
+
+fn export_mesh(path: &Path, input: Option<&String>) -> Result<(), Box<dyn std::error::Error>> {
+    let format = day18::MeshFormat::from_path(path).ok_or("the mesh file has to end in .obj or .stl")?;
+    let input = match input {
+        Some(input) => std::fs::read_to_string(input)?,
+        None => std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/day18/input.txt"))?,
+    };
+    std::fs::write(path, day18::export_mesh(&input, format))?;
+    println!("Saved the droplet to {}: {}", path.display(), day18::report(&input));
+    Ok(())
+}
+
+const USAGE: &str = "usage: aoc2022 [--explain] [<day>]
+       aoc2022 --batch[=<dir>]
+       aoc2022 --mesh=<file.obj|file.stl> [<input>]
+       aoc2022 --download [--force] <day>
+       aoc2022 --submit <day> <part> <answer>";
+
+fn known_flag(flag: &str) -> bool {
+    matches!(flag, "--explain" | "--batch" | "--download" | "--force" | "--submit")
+        || flag.starts_with("--batch=")
+        || flag.starts_with("--mesh=")
+}
+
 fn main() {
-    if let Some(day_string) = env::args().nth(1) {
+    let (flags, args): (Vec<_>, Vec<_>) = env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    // A mistyped flag would otherwise be ignored, and the run would quietly go ahead without it
+    if let Some(flag) = flags.iter().find(|flag| !known_flag(flag)) {
+        eprintln!("Unknown flag {flag}\n{USAGE}");
+        std::process::exit(2);
+    }
+    init_tracing(flags.iter().any(|flag| flag == "--explain"));
+    // `--batch` runs every input under `inputs/` (or `--batch=<dir>`) instead of the bundled ones
+    if let Some(flag) = flags.iter().find(|flag| flag.starts_with("--batch")) {
+        let root = flag.strip_prefix("--batch=").unwrap_or("inputs");
+        match batch::run(Path::new(root)) {
+            Ok(results) => print!("{}", batch::Matrix(&results)),
+            Err(e) => eprintln!("Couldn't read inputs from {root}: {e}"),
+        }
+        return;
+    }
+    // `--mesh=<file>` saves the outside of the day 18 droplet (in `<input>`, or the bundled input) as an OBJ or STL
+    // file, depending on the extension, and reports the air trapped inside it
+    if let Some(flag) = flags.iter().find(|flag| flag.starts_with("--mesh=")) {
+        if let Err(e) = export_mesh(Path::new(flag.trim_start_matches("--mesh=")), args.first()) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+    // `--download <day>` saves the day's input next to its solver, though only over an existing one with `--force`;
+    // `--submit <day> <part> <answer>` sends an answer
+    if flags
+        .iter()
+        .any(|flag| flag == "--download" || flag == "--submit")
+    {
+        let has_flag = |name: &str| flags.iter().any(|flag| flag == name);
+        if let Err(e) = talk_to_server(has_flag("--submit"), has_flag("--force"), &args) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+    if let Some(day_string) = args.first() {
         dispatch_day(day_string.parse::<usize>().unwrap());
     } else {
         let total_time = (1..=25).map(dispatch_day).sum::<u128>();
@@ -0,0 +1,153 @@
+//! Allocation accounting for the runner.
+//!
+//! With the `alloc-stats` feature enabled, `main.rs` installs [`CountingAllocator`] as the global allocator and
+//! wraps each day in [`profile`]. Each day's `run()` marks its parts with [`part`], so the runner can report the
+//! allocations made by each part alongside the day as a whole. Without the feature, [`part`] just calls the closure
+//! and [`profile`] reports nothing.
+
+use std::fmt::Display;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocStats {
+    pub peak_bytes: usize,
+    pub total_bytes: usize,
+    pub allocations: usize,
+}
+
+impl Display for AllocStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "peak {} bytes, {} bytes in {} allocations", self.peak_bytes, self.total_bytes, self.allocations)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub total: AllocStats,
+    pub parts: Vec<(&'static str, AllocStats)>,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "    total: {}", self.total)?;
+        for (label, stats) in &self.parts {
+            write!(f, "\n    {label}: {stats}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+mod counting {
+    use super::{AllocStats, Report};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    static CURRENT_BYTES: AtomicUsize = AtomicUsize::new(0);
+    static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+    static TOTAL_BYTES: AtomicUsize = AtomicUsize::new(0);
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    static PARTS: Mutex<Vec<(&'static str, AllocStats)>> = Mutex::new(Vec::new());
+
+    /// Wraps the system allocator, counting every allocation and tracking the high-water mark of live bytes.
+    pub struct CountingAllocator;
+
+    fn record_alloc(size: usize) {
+        let current = CURRENT_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK_BYTES.fetch_max(current, Ordering::Relaxed);
+        TOTAL_BYTES.fetch_add(size, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(size: usize) {
+        CURRENT_BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                record_alloc(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc_zeroed(layout);
+            if !ptr.is_null() {
+                record_alloc(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            record_dealloc(layout.size());
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                record_dealloc(layout.size());
+                record_alloc(new_size);
+            }
+            new_ptr
+        }
+    }
+
+    /// Runs `f`, returning the allocations it made. The peak is measured relative to the live bytes when `f`
+    /// started; nested calls keep the outer peak intact.
+    pub(super) fn measure<T>(f: impl FnOnce() -> T) -> (T, AllocStats) {
+        let start_bytes = CURRENT_BYTES.load(Ordering::Relaxed);
+        let outer_peak = PEAK_BYTES.swap(start_bytes, Ordering::Relaxed);
+        let start_total = TOTAL_BYTES.load(Ordering::Relaxed);
+        let start_allocations = ALLOCATIONS.load(Ordering::Relaxed);
+
+        let result = f();
+
+        let inner_peak = PEAK_BYTES.fetch_max(outer_peak, Ordering::Relaxed);
+        let stats = AllocStats {
+            peak_bytes: inner_peak.saturating_sub(start_bytes),
+            total_bytes: TOTAL_BYTES.load(Ordering::Relaxed) - start_total,
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - start_allocations,
+        };
+        (result, stats)
+    }
+
+    pub(super) fn part<T>(label: &'static str, f: impl FnOnce() -> T) -> T {
+        let (result, stats) = measure(f);
+        PARTS.lock().unwrap().push((label, stats));
+        result
+    }
+
+    pub(super) fn profile<T>(f: impl FnOnce() -> T) -> (T, Option<Report>) {
+        PARTS.lock().unwrap().clear();
+        let (result, total) = measure(f);
+        let parts = std::mem::take(&mut *PARTS.lock().unwrap());
+        (result, Some(Report { total, parts }))
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+pub use counting::CountingAllocator;
+
+/// Evaluates one part of a day's puzzle, recording its allocations under `label` when profiling.
+#[inline]
+pub fn part<T>(label: &'static str, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "alloc-stats")]
+    return counting::part(label, f);
+    #[cfg(not(feature = "alloc-stats"))]
+    {
+        let _ = label;
+        f()
+    }
+}
+
+/// Runs a whole day, returning an allocation report for it and each of its parts when profiling.
+#[inline]
+pub fn profile<T>(f: impl FnOnce() -> T) -> (T, Option<Report>) {
+    #[cfg(feature = "alloc-stats")]
+    return counting::profile(f);
+    #[cfg(not(feature = "alloc-stats"))]
+    (f(), None)
+}